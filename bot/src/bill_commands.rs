@@ -1,12 +1,13 @@
 use crate::bot_data::*;
+use crate::create_commands::*;
 use crate::utilities::*;
 use crate::CheeseCoinTy;
 use discord::*;
 
-pub async fn create_bill(handler_data: &mut HandlerData<'_>) {
-	let bill_name = handler_data.options["name"].as_str();
+pub async fn create_bill(handler_data: &mut HandlerData<'_>, command: BillCreate) {
+	let bill_name = command.name;
 
-	let cheesecoin = handler_data.options["cheesecoin"].as_float() * 100.;
+	let cheesecoin = command.cheesecoin * 100.;
 	if !cheesecoin.is_finite() || cheesecoin < 0. || cheesecoin >= CheeseCoinTy::MAX as f64 {
 		respond_with_embed(
 			handler_data,
@@ -20,7 +21,7 @@ pub async fn create_bill(handler_data: &mut HandlerData<'_>) {
 	let cheesecoin = cheesecoin as CheeseCoinTy;
 
 	let bot_data = &mut handler_data.bot_data;
-	let to = match account_option(*bot_data, &handler_data.options["to"], BotData::account_owned, &handler_data.user).await {
+	let to = match account_option(bot_data, &command.to, BotData::account_owned, &handler_data.user).await {
 		Some(x) => x,
 		None => {
 			respond_with_embed(handler_data, Embed::standard().with_title("Create Bill").with_description("Invalid to.")).await;
@@ -28,7 +29,7 @@ pub async fn create_bill(handler_data: &mut HandlerData<'_>) {
 		}
 	};
 
-	let days = handler_data.options["days"].as_float();
+	let days = command.days;
 	if !days.is_finite() || days < 1. || days >= i32::MAX as f64 {
 		respond_with_embed(
 			handler_data,
@@ -74,10 +75,10 @@ pub async fn create_bill(handler_data: &mut HandlerData<'_>) {
 	respond_with_embed(handler_data, Embed::standard().with_title("Create Bill").with_description(description)).await;
 }
 
-pub async fn bill_delete(handler_data: &mut HandlerData<'_>) {
+pub async fn bill_delete(handler_data: &mut HandlerData<'_>, command: BillDelete) {
 	let bot_data = &mut handler_data.bot_data;
 
-	let bill_id = match account_option(bot_data, &handler_data.options["name"], BotData::bill_owned, &handler_data.user).await {
+	let bill_id = match account_option(bot_data, &command.name, BotData::bill_owned, &handler_data.user).await {
 		Some(x) => x,
 		None => {
 			respond_with_embed(
@@ -106,10 +107,10 @@ pub async fn bill_delete(handler_data: &mut HandlerData<'_>) {
 	respond_with_embed(handler_data, Embed::standard().with_title("Deleted bill").with_description(description)).await;
 }
 
-pub async fn bill_subscribe(handler_data: &mut HandlerData<'_>) {
+pub async fn bill_subscribe(handler_data: &mut HandlerData<'_>, command: BillSubscribe) {
 	let bot_data = &mut handler_data.bot_data;
 
-	let bill_id = match account_option(bot_data, &handler_data.options["name"], BotData::bill_exists, &handler_data.user).await {
+	let bill_id = match account_option(bot_data, &command.name, BotData::bill_exists, &handler_data.user).await {
 		Some(x) => x,
 		None => {
 			respond_with_embed(
@@ -121,7 +122,7 @@ pub async fn bill_subscribe(handler_data: &mut HandlerData<'_>) {
 		}
 	};
 
-	let from = match account_option(bot_data, &handler_data.options["from"], BotData::account_owned, &handler_data.user).await {
+	let from = match account_option(bot_data, &command.from, BotData::account_owned, &handler_data.user).await {
 		Some(x) => x,
 		None => {
 			respond_with_embed(
@@ -165,7 +166,7 @@ pub async fn bill_subscribe(handler_data: &mut HandlerData<'_>) {
 		)
 		.await
 		{
-			error!("Failed to notify new sub {owner} {e:?}");
+			log_dm_error(&e, format_args!("Failed to notify new sub {owner}"));
 		}
 	}
 
@@ -176,10 +177,10 @@ pub async fn bill_subscribe(handler_data: &mut HandlerData<'_>) {
 	.await;
 }
 
-pub async fn bill_unsubscribe(handler_data: &mut HandlerData<'_>) {
+pub async fn bill_unsubscribe(handler_data: &mut HandlerData<'_>, command: BillUnsubscribe) {
 	let bot_data = &mut handler_data.bot_data;
 
-	let bill_id = match account_option(bot_data, &handler_data.options["name"], BotData::bill_subscribed, &handler_data.user).await {
+	let bill_id = match account_option(bot_data, &command.name, BotData::bill_subscribed, &handler_data.user).await {
 		Some(x) => x,
 		None => {
 			respond_with_embed(
@@ -230,7 +231,7 @@ pub async fn bill_unsubscribe(handler_data: &mut HandlerData<'_>) {
 		)
 		.await
 		{
-			error!("Failed to notify {owner} of unsub {e:?}");
+			log_dm_error(&e, format_args!("Failed to notify {owner} of unsub"));
 		}
 	}
 
@@ -244,67 +245,50 @@ pub async fn bill_unsubscribe(handler_data: &mut HandlerData<'_>) {
 pub async fn bill_view(handler_data: &mut HandlerData<'_>) {
 	let bot_data = &mut handler_data.bot_data;
 
-	let mut result = format!("**Bills your accounts are subscribed to**");
+	let mut embed = Embed::standard().with_title("Your bills");
 	let user_owned_accounts = {
 		let user = bot_data.cheese_user(&handler_data.user);
 		user.organisations.iter().copied().chain([user.account]).collect::<Vec<_>>()
 	};
+	let account_name = |account: AccountId| bot_data.accounts.account(account).map_or("No owner", |account| &account.name).to_string();
 
+	// A field for each account listing the bills it is subscribed to
 	let mut subscribed_bills = false;
 	for account in &user_owned_accounts {
 		let Some(account) = bot_data.accounts.account(*account) else {
 			continue;
 		};
-		if account.subscribed_bills.is_empty() {
-			continue;
-		}
-		let _ = write!(result, "\n{}:\n```", account.name);
-
-		for bill_id in &account.subscribed_bills {
-			if let Some(bill) = bot_data.bills.get(&bill_id) {
-				let owner = bot_data
-					.accounts
-					.account(bill.owner)
-					.map_or_else(|| "No owner".to_string(), |owner| owner.name.clone());
-				let _ = write!(result, "\n{}", format_bill(bill, owner));
-				subscribed_bills = true;
-			}
+		let bills = account.subscribed_bills.iter().filter_map(|bill_id| bot_data.bills.get(bill_id));
+		let bills = bills.map(|bill| format_bill(bill, account_name(bill.owner))).collect::<Vec<_>>();
+		if !bills.is_empty() {
+			embed = embed.with_field(format!("{} is subscribed to", account.name), bills.join("\n"), false);
+			subscribed_bills = true;
 		}
-		let _ = write!(result, "\n```");
 	}
 	if !subscribed_bills {
-		result += "\nNone";
+		embed = embed.with_field("Subscribed bills", "None", false);
 	}
 
-	let _ = write!(result, "\n\n**Owned Bills**");
+	// A field for each owned bill listing the subscribers
 	let mut owned_bills = false;
 	for account in &user_owned_accounts {
 		let Some(account) = bot_data.accounts.account(*account) else {
 			continue;
 		};
-		for bill_id in &account.owned_bills {
-			if let Some(bill) = bot_data.bills.get(&bill_id) {
-				let _ = write!(result, "\n{}", format_bill(bill, account.name.clone()));
-				result += "\nSubscribers:";
-				result += "\n```";
-
-				if bill.subscribers.is_empty() {
-					result += "\nNone";
-				} else {
-					for subscriber in &bill.subscribers {
-						result += "\n";
-						result += &bot_data.accounts.account(*subscriber).map_or("No owner", |account| &account.name);
-					}
-				}
-
-				owned_bills = true;
-				result += "\n```";
-			}
+		for bill in account.owned_bills.iter().filter_map(|bill_id| bot_data.bills.get(bill_id)) {
+			let subscribers = bill.subscribers.iter().map(|&subscriber| account_name(subscriber)).collect::<Vec<_>>();
+			let subscribers = if subscribers.is_empty() {
+				"No subscribers".to_string()
+			} else {
+				format!("Subscribers: {}", subscribers.join(", "))
+			};
+			embed = embed.with_field(format_bill(bill, account.name.clone()), subscribers, false);
+			owned_bills = true;
 		}
 	}
 	if !owned_bills {
-		result += "\nNone";
+		embed = embed.with_field("Owned bills", "None", false);
 	}
 
-	respond_with_embed(handler_data, Embed::standard().with_title("Your bills").with_description(result)).await;
+	respond_with_embed(handler_data, embed).await;
 }
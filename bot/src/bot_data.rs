@@ -55,14 +55,14 @@ impl Accounts {
 	pub fn account_mut(&mut self, account: AccountId) -> Option<&mut Account> {
 		self.personal_accounts
 			.get_mut(&account)
-			.map_or_else(|| self.organisation_accounts.get_mut(&account), |x| Some(x))
+			.map_or_else(|| self.organisation_accounts.get_mut(&account), Some)
 	}
 
 	/// Get the account from an account id (either personal or organisation)
 	pub fn account(&self, account: AccountId) -> Option<&Account> {
 		self.personal_accounts
 			.get(&account)
-			.map_or_else(|| self.organisation_accounts.get(&account), |x| Some(x))
+			.map_or_else(|| self.organisation_accounts.get(&account), Some)
 	}
 
 	pub fn exists(&self, account: AccountId) -> bool {
@@ -107,6 +107,9 @@ pub struct BotData {
 	pub previous_results: String,
 	#[serde(skip)]
 	pub file_path: String,
+	/// The latency of the last gateway heartbeat
+	#[serde(skip)]
+	pub gateway_latency: Option<std::time::Duration>,
 	#[serde(default)]
 	pub decree: u64,
 }
@@ -139,6 +142,7 @@ impl Default for BotData {
 			previous_time: chrono::Utc::now(),
 			previous_results: "No previous results".into(),
 			file_path: String::new(),
+			gateway_latency: None,
 			decree: 0,
 		}
 	}
@@ -233,8 +237,8 @@ impl BotData {
 
 	/// Computes the total currency in circulation (for currency information in balances)
 	pub fn total_currency(&self) -> CheeseCoinTy {
-		let personal = self.accounts.personal_accounts.iter().map(|(_, a)| a.balance);
-		let orgs = self.accounts.organisation_accounts.iter().map(|(_, a)| a.balance);
+		let personal = self.accounts.personal_accounts.values().map(|a| a.balance);
+		let orgs = self.accounts.organisation_accounts.values().map(|a| a.balance);
 		personal.chain(orgs).fold(0, |a, b| a.saturating_add(b))
 	}
 
@@ -306,7 +310,7 @@ impl BotData {
 			.chain([user.account])
 			.filter_map(|account_id| self.accounts.account(account_id))
 			.flat_map(|account| account.owned_bills.iter().map(|bill| (bill, account.name.clone())))
-			.filter_map(|(bill_id, account_name)| self.bills.get(&bill_id).map(|bill| (bill, account_name, bill_id)))
+			.filter_map(|(bill_id, account_name)| self.bills.get(bill_id).map(|bill| (bill, account_name, bill_id)))
 			.map(|(bill, account_name, &bill_id)| (format_bill(bill, account_name), bill_id))
 	}
 	/// List the user's subscribed bills
@@ -318,7 +322,7 @@ impl BotData {
 			.chain([user.account])
 			.filter_map(|account_id| self.accounts.account(account_id))
 			.flat_map(|account| account.subscribed_bills.iter().map(|bill| (bill, account.name.clone())))
-			.filter_map(|(bill_id, account_name)| self.bills.get(&bill_id).map(|bill| (bill, account_name, bill_id)))
+			.filter_map(|(bill_id, account_name)| self.bills.get(bill_id).map(|bill| (bill, account_name, bill_id)))
 			.map(|(bill, account_name, &bill_id)| (format_bill(bill, account_name), bill_id))
 	}
 	/// List the user's owned bills
@@ -331,7 +335,7 @@ impl BotData {
 
 	/// List the parties
 	pub fn parties(&self) -> impl Iterator<Item = String> + '_ {
-		self.parties.keys().map(|v| v.clone())
+		self.parties.keys().cloned()
 	}
 
 	pub fn save(&self) {
@@ -348,5 +352,11 @@ pub struct HandlerData<'a> {
 	pub bot_data: &'a mut BotData,
 	pub interaction: Interaction,
 	pub user: User,
-	pub options: HashMap<String, OptionType>,
+	pub options: CommandOptions,
+	/// If the response has been deferred (so responding edits the original response instead)
+	pub deferred: bool,
+	/// The custom id of the message component that was used (if any)
+	pub custom_id: Option<CustomId>,
+	/// The values chosen in a select menu
+	pub values: Vec<String>,
 }
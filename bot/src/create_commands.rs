@@ -1,363 +1,332 @@
 use discord::*;
 
-pub async fn create_commands(client: &mut DiscordClient, application_id: &String) {
-	let about = ApplicationCommand::new()
-		.with_command_type(CommandType::Chat)
-		.with_name("about")
-		.with_description("Description of the bot.");
-	let balances = ApplicationCommand::new()
-		.with_command_type(CommandType::Chat)
-		.with_name("balances")
-		.with_description("All of your balances.");
-	let pay = ApplicationCommand::new()
-		.with_command_type(CommandType::Chat)
-		.with_name("pay")
-		.with_description("Give someone cheesecoins.")
-		.with_options(
-			ApplicationCommandOption::new()
-				.with_option_type(CommandOptionType::String)
-				.with_name("recipient")
-				.with_description("recipient of the payment")
-				.with_required(true)
-				.with_autocomplete(true),
-		)
-		.with_options(
-			ApplicationCommandOption::new()
-				.with_option_type(CommandOptionType::Number)
-				.with_name("cheesecoin")
-				.with_description("Number of cheesecoin")
-				.with_required(true),
-		)
-		.with_options(
-			ApplicationCommandOption::new()
-				.with_option_type(CommandOptionType::String)
-				.with_name("from")
-				.with_description("The account the cheesecoins are from")
-				.with_required(true)
-				.with_autocomplete(true),
-		);
-	let organisation = ApplicationCommand::new()
-		.with_command_type(CommandType::Chat)
-		.with_name("organisation")
-		.with_description("Organisation commands")
-		.with_options(
-			ApplicationCommandOption::new()
-				.with_name("create")
-				.with_description("Create an organisation.")
-				.with_options(
-					ApplicationCommandOption::new()
-						.with_option_type(CommandOptionType::String)
-						.with_name("name")
-						.with_required(true)
-						.with_description("The name of the new organisation"),
-				),
-		)
-		.with_options(
-			ApplicationCommandOption::new()
-				.with_name("transfer")
-				.with_description("Transfer an organisation")
-				.with_options(
-					ApplicationCommandOption::new()
-						.with_option_type(CommandOptionType::String)
-						.with_name("name")
-						.with_required(true)
-						.with_description("The name of the organisation")
-						.with_autocomplete(true),
-				)
-				.with_options(
-					ApplicationCommandOption::new()
-						.with_option_type(CommandOptionType::String)
-						.with_name("owner")
-						.with_required(true)
-						.with_description("The new owner of the organisation")
-						.with_autocomplete(true),
-				),
-		)
-		.with_options(
-			ApplicationCommandOption::new()
-				.with_name("rename")
-				.with_description("Rename an organisation")
-				.with_options(
-					ApplicationCommandOption::new()
-						.with_option_type(CommandOptionType::String)
-						.with_name("name")
-						.with_required(true)
-						.with_description("The name of the organisation")
-						.with_autocomplete(true),
-				)
-				.with_options(
-					ApplicationCommandOption::new()
-						.with_option_type(CommandOptionType::String)
-						.with_name("new")
-						.with_required(true)
-						.with_description("The new name of the organisation"),
-				),
-		)
-		.with_options(
-			ApplicationCommandOption::new()
-				.with_name("delete")
-				.with_description("Delete an organisation")
-				.with_options(
-					ApplicationCommandOption::new()
-						.with_option_type(CommandOptionType::String)
-						.with_name("name")
-						.with_required(true)
-						.with_description("The name of the organisation")
-						.with_autocomplete(true),
-				),
-		);
-	let bills = ApplicationCommand::new()
-		.with_command_type(CommandType::Chat)
-		.with_name("bill")
-		.with_description("Bill commands")
-		.with_options(
-			ApplicationCommandOption::new()
-				.with_name("create")
-				.with_description("Create a bill.")
-				.with_options(
-					ApplicationCommandOption::new()
-						.with_option_type(CommandOptionType::String)
-						.with_name("name")
-						.with_required(true)
-						.with_description("The name of the new bill"),
-				)
-				.with_options(
-					ApplicationCommandOption::new()
-						.with_option_type(CommandOptionType::Number)
-						.with_name("cheesecoin")
-						.with_description("Number of cheesecoin")
-						.with_required(true),
-				)
-				.with_options(
-					ApplicationCommandOption::new()
-						.with_option_type(CommandOptionType::String)
-						.with_name("to")
-						.with_description("The account the cheesecoins go to")
-						.with_required(true)
-						.with_autocomplete(true),
-				)
-				.with_options(
-					ApplicationCommandOption::new()
-						.with_option_type(CommandOptionType::Number)
-						.with_name("days")
-						.with_description("Days between payments")
-						.with_required(true),
-				),
-		)
-		.with_options(
-			ApplicationCommandOption::new()
-				.with_name("delete")
-				.with_description("Delete a bill")
-				.with_options(
-					ApplicationCommandOption::new()
-						.with_option_type(CommandOptionType::String)
-						.with_name("name")
-						.with_required(true)
-						.with_description("The name of the bill")
-						.with_autocomplete(true),
-				),
-		)
-		.with_options(
-			ApplicationCommandOption::new()
-				.with_name("subscribe")
-				.with_description("Subscribe to a bill")
-				.with_options(
-					ApplicationCommandOption::new()
-						.with_option_type(CommandOptionType::String)
-						.with_name("name")
-						.with_required(true)
-						.with_description("The name of the bill")
-						.with_autocomplete(true),
-				)
-				.with_options(
-					ApplicationCommandOption::new()
-						.with_option_type(CommandOptionType::String)
-						.with_name("from")
-						.with_description("The account the cheesecoins are paid from")
-						.with_required(true)
-						.with_autocomplete(true),
-				),
-		)
-		.with_options(
-			ApplicationCommandOption::new()
-				.with_name("unsubscribe")
-				.with_description("Unsubscribe from a bill")
-				.with_options(
-					ApplicationCommandOption::new()
-						.with_option_type(CommandOptionType::String)
-						.with_name("name")
-						.with_required(true)
-						.with_description("The name of the bill")
-						.with_autocomplete(true),
-				),
-		)
-		.with_options(ApplicationCommandOption::new().with_name("view").with_description("View active bills"));
-	let rollcall = ApplicationCommand::new()
-		.with_command_type(CommandType::Chat)
-		.with_name("claim")
-		.with_description("Claim commands")
-		.with_options(
-			ApplicationCommandOption::new()
-				.with_name("rollcall")
-				.with_description("Claim your daily citizen rollcall"),
-		);
-
-	let party_option = ApplicationCommandOption::new()
-		.with_option_type(CommandOptionType::String)
-		.with_name("party")
-		.with_required(true)
-		.with_description("The party")
-		.with_autocomplete(true);
-
-	let parliament = ApplicationCommand::new()
-		.with_command_type(CommandType::Chat)
-		.with_name("parliament")
-		.with_description("Parliament commands")
-		.with_options(
-			ApplicationCommandOption::new()
-				.with_option_type(CommandOptionType::SubCommandGroup)
-				.with_name("add")
-				.with_description("Add something.")
-				.with_options(
-					ApplicationCommandOption::new()
-						.with_name("party")
-						.with_description("Add a party to the election.")
-						.with_options(party_option.clone().with_autocomplete(false)),
-				),
-		)
-		.with_options(
-			ApplicationCommandOption::new()
-				.with_option_type(CommandOptionType::SubCommandGroup)
-				.with_name("delete")
-				.with_description("Delete something.")
-				.with_options(
-					ApplicationCommandOption::new()
-						.with_name("party")
-						.with_description("Delete a party from the election.")
-						.with_options(party_option.clone()),
-				),
-		)
-		.with_options(
-			ApplicationCommandOption::new()
-				.with_name("vote")
-				.with_description("Vote for a candidate (or change your vote).")
-				.with_options(party_option.clone()),
-		)
-		.with_options(
-			ApplicationCommandOption::new()
-				.with_option_type(CommandOptionType::SubCommandGroup)
-				.with_name("view")
-				.with_description("View something.")
-				.with_options(
-					ApplicationCommandOption::new()
-						.with_name("results")
-						.with_description("View results of last election."),
-				),
-		)
-		.with_options(
-			ApplicationCommandOption::new()
-				.with_option_type(CommandOptionType::SubCommandGroup)
-				.with_name("count")
-				.with_description("Count something.")
-				.with_options(
-					ApplicationCommandOption::new()
-						.with_name("results")
-						.with_description("Count results of last election."),
-				),
-		);
-
-	let role = ApplicationCommand::new()
-		.with_command_type(CommandType::Chat)
-		.with_name("role")
-		.with_description("Cosmetic role options (15cc)")
-		.with_options(
-			ApplicationCommandOption::new()
-				.with_name("assign")
-				.with_description("Assign yourself a cosmetic role for a fee.")
-				.with_options(
-					ApplicationCommandOption::new()
-						.with_option_type(CommandOptionType::Number)
-						.with_name("r")
-						.with_description("Red (0-255)")
-						.with_required(true),
-				)
-				.with_options(
-					ApplicationCommandOption::new()
-						.with_option_type(CommandOptionType::Number)
-						.with_name("g")
-						.with_description("Green (0-255)")
-						.with_required(true),
-				)
-				.with_options(
-					ApplicationCommandOption::new()
-						.with_option_type(CommandOptionType::Number)
-						.with_name("b")
-						.with_description("Blue (0-255)")
-						.with_required(true),
-				),
-		);
-	let decree = ApplicationCommand::new()
-		.with_name("decree")
-		.with_description("Decree (si tu es président)")
-		.with_options(
-			ApplicationCommandOption::new()
-				.with_option_type(CommandOptionType::String)
-				.with_name("title")
-				.with_description("Title of decree")
-				.with_required(true),
-		)
-		.with_options(
-			ApplicationCommandOption::new()
-				.with_option_type(CommandOptionType::String)
-				.with_name("description")
-				.with_description("Description of decree")
-				.with_required(true),
-		);
-
-	let sudo = ApplicationCommand::new()
-		.with_command_type(CommandType::Chat)
-		.with_name("sudo")
-		.with_description("super user do")
-		.with_options(
-			ApplicationCommandOption::new()
-				.with_option_type(CommandOptionType::SubCommandGroup)
-				.with_name("print")
-				.with_description("Utilise a printer.")
-				.with_options(
-					ApplicationCommandOption::new()
-						.with_option_type(CommandOptionType::SubCommand)
-						.with_name("cheesecoin")
-						.with_description("Print cheesecoin with a printer.")
-						.with_options(
-							ApplicationCommandOption::new()
-								.with_option_type(CommandOptionType::String)
-								.with_name("recipient")
-								.with_description("recipient of the payment")
-								.with_required(true)
-								.with_autocomplete(true),
-						)
-						.with_options(
-							ApplicationCommandOption::new()
-								.with_option_type(CommandOptionType::Number)
-								.with_name("cheesecoin")
-								.with_description("Number of cheesecoin")
-								.with_required(true),
-						),
-				),
-		);
-
-	ApplicationCommandList::new()
-		.with_commands(about)
-		.with_commands(balances)
-		.with_commands(pay)
-		.with_commands(bills)
-		.with_commands(rollcall)
-		.with_commands(organisation)
-		.with_commands(parliament)
-		.with_commands(role)
-		.with_commands(decree)
-		.with_commands(sudo)
-		.put_bulk_override_global(client, application_id)
-		.await
-		.unwrap();
+use crate::bot_data::BotData;
+
+/// Finds the choices for an autocompleted option
+pub type Choices = fn(&BotData, &User) -> Vec<ApplicationCommandOptionChoice>;
+
+/// Every command, registered with [`create_commands`] and parsed in `handle_interaction`
+#[derive(CommandList)]
+pub enum Command {
+	About(About),
+	Balances(Balances),
+	Pay(Pay),
+	Bill(BillCommand),
+	Claim(Claim),
+	Organisation(Organisation),
+	Parliament(Parliament),
+	Role(RoleCommand),
+	Decree(Decree),
+	Sudo(Sudo),
+}
+
+/// Description of the bot.
+#[derive(SlashCommand)]
+pub struct About;
+
+/// All of your balances.
+#[derive(SlashCommand)]
+pub struct Balances;
+
+/// Give someone cheesecoins.
+#[derive(SlashCommand)]
+#[command(autocomplete = Choices)]
+pub struct Pay {
+	/// recipient of the payment
+	#[command(autocomplete = accounts)]
+	pub recipient: String,
+	/// Number of cheesecoin
+	pub cheesecoin: f64,
+	/// The account the cheesecoins are from
+	#[command(autocomplete = owned_accounts)]
+	pub from: String,
+}
+
+/// Organisation commands
+#[derive(SlashCommand)]
+pub enum Organisation {
+	/// Create an organisation.
+	Create(OrganisationCreate),
+	/// Transfer an organisation
+	Transfer(OrganisationTransfer),
+	/// Rename an organisation
+	Rename(OrganisationRename),
+	/// Delete an organisation
+	Delete(OrganisationDelete),
+}
+
+#[derive(SlashCommand)]
+pub struct OrganisationCreate {
+	/// The name of the new organisation
+	pub name: String,
+}
+
+#[derive(SlashCommand)]
+#[command(autocomplete = Choices)]
+pub struct OrganisationTransfer {
+	/// The name of the organisation
+	#[command(autocomplete = owned_orgs)]
+	pub name: String,
+	/// The new owner of the organisation
+	#[command(autocomplete = people)]
+	pub owner: String,
+}
+
+#[derive(SlashCommand)]
+#[command(autocomplete = Choices)]
+pub struct OrganisationRename {
+	/// The name of the organisation
+	#[command(autocomplete = owned_orgs)]
+	pub name: String,
+	/// The new name of the organisation
+	pub new: String,
+}
+
+#[derive(SlashCommand)]
+#[command(autocomplete = Choices)]
+pub struct OrganisationDelete {
+	/// The name of the organisation
+	#[command(autocomplete = owned_orgs)]
+	pub name: String,
+}
+
+/// Bill commands
+#[derive(SlashCommand)]
+#[command(name = "bill")]
+pub enum BillCommand {
+	/// Create a bill.
+	Create(BillCreate),
+	/// Delete a bill
+	Delete(BillDelete),
+	/// Subscribe to a bill
+	Subscribe(BillSubscribe),
+	/// Unsubscribe from a bill
+	Unsubscribe(BillUnsubscribe),
+	/// View active bills
+	View,
+}
+
+#[derive(SlashCommand)]
+#[command(autocomplete = Choices)]
+pub struct BillCreate {
+	/// The name of the new bill
+	pub name: String,
+	/// Number of cheesecoin
+	pub cheesecoin: f64,
+	/// The account the cheesecoins go to
+	#[command(autocomplete = owned_accounts)]
+	pub to: String,
+	/// Days between payments
+	pub days: f64,
+}
+
+#[derive(SlashCommand)]
+#[command(autocomplete = Choices)]
+pub struct BillDelete {
+	/// The name of the bill
+	#[command(autocomplete = owned_bills)]
+	pub name: String,
+}
+
+#[derive(SlashCommand)]
+#[command(autocomplete = Choices)]
+pub struct BillSubscribe {
+	/// The name of the bill
+	#[command(autocomplete = bills)]
+	pub name: String,
+	/// The account the cheesecoins are paid from
+	#[command(autocomplete = owned_accounts)]
+	pub from: String,
+}
+
+#[derive(SlashCommand)]
+#[command(autocomplete = Choices)]
+pub struct BillUnsubscribe {
+	/// The name of the bill
+	#[command(autocomplete = subscribed_bills)]
+	pub name: String,
+}
+
+/// Claim commands
+#[derive(SlashCommand)]
+pub enum Claim {
+	/// Claim your daily citizen rollcall
+	Rollcall,
+}
+
+/// Parliament commands
+#[derive(SlashCommand)]
+pub enum Parliament {
+	/// Add something.
+	Add(ParliamentAdd),
+	/// Delete something.
+	Delete(ParliamentDelete),
+	/// Vote for a candidate (or change your vote), leave blank for a ballot.
+	Vote(Vote),
+	/// View something.
+	View(ParliamentView),
+}
+
+#[derive(SlashCommand)]
+pub enum ParliamentAdd {
+	/// Add a party to the election.
+	Party(AddParty),
+}
+
+#[derive(SlashCommand)]
+pub struct AddParty {
+	/// The party
+	#[command(max_length = 100)]
+	pub party: String,
+}
+
+#[derive(SlashCommand)]
+pub enum ParliamentDelete {
+	/// Delete a party from the election.
+	Party(DeleteParty),
+}
+
+#[derive(SlashCommand)]
+#[command(autocomplete = Choices)]
+pub struct DeleteParty {
+	/// The party
+	#[command(autocomplete = parties)]
+	pub party: String,
+}
+
+#[derive(SlashCommand)]
+#[command(autocomplete = Choices)]
+pub struct Vote {
+	/// The party
+	#[command(autocomplete = parties)]
+	pub party: Option<String>,
+}
+
+#[derive(SlashCommand)]
+pub enum ParliamentView {
+	/// View results of last election.
+	Results,
+}
+
+/// Cosmetic role options (15cc)
+#[derive(SlashCommand)]
+#[command(name = "role")]
+pub enum RoleCommand {
+	/// Assign yourself a cosmetic role for a fee.
+	Assign(RoleAssign),
+}
+
+#[derive(SlashCommand)]
+pub struct RoleAssign {
+	/// Red (0-255)
+	#[command(range = 0..=255)]
+	pub r: i64,
+	/// Green (0-255)
+	#[command(range = 0..=255)]
+	pub g: i64,
+	/// Blue (0-255)
+	#[command(range = 0..=255)]
+	pub b: i64,
+}
+
+/// Decree (si tu es président)
+#[derive(SlashCommand)]
+pub struct Decree;
+
+/// super user do
+#[derive(SlashCommand)]
+// Only shown to administrators (who can allow other roles in the server settings)
+#[command(default_member_permissions = Permissions::empty())]
+pub enum Sudo {
+	/// Utilise a printer.
+	Print(SudoPrint),
+	/// Count something.
+	Count(SudoCount),
+}
+
+#[derive(SlashCommand)]
+pub enum SudoPrint {
+	/// Print cheesecoin with a printer.
+	Cheesecoin(PrintCheesecoin),
+}
+
+#[derive(SlashCommand)]
+#[command(autocomplete = Choices)]
+pub struct PrintCheesecoin {
+	/// recipient of the payment
+	#[command(autocomplete = accounts)]
+	pub recipient: String,
+	/// Number of cheesecoin
+	pub cheesecoin: f64,
+}
+
+#[derive(SlashCommand)]
+pub enum SudoCount {
+	/// Count results of last election.
+	Results,
+}
+
+/// Choices with the name of an account (or bill) and its id as the value
+fn id_choices(choices: impl Iterator<Item = (String, u64)>) -> Vec<ApplicationCommandOptionChoice> {
+	choices
+		.map(|(name, id)| {
+			ApplicationCommandOptionChoice::new()
+				.with_name(name.chars().take(100).collect::<String>())
+				.with_value(OptionType::String(id.to_string()))
+		})
+		.collect()
+}
+
+fn accounts(bot_data: &BotData, _user: &User) -> Vec<ApplicationCommandOptionChoice> {
+	id_choices(bot_data.personal_accounts().chain(bot_data.organisation_accounts()))
+}
+
+fn owned_accounts(bot_data: &BotData, user: &User) -> Vec<ApplicationCommandOptionChoice> {
+	id_choices(bot_data.personal_account(user).chain(bot_data.owned_orgs(user)))
+}
+
+fn owned_orgs(bot_data: &BotData, user: &User) -> Vec<ApplicationCommandOptionChoice> {
+	id_choices(bot_data.owned_orgs(user))
+}
+
+fn people(bot_data: &BotData, user: &User) -> Vec<ApplicationCommandOptionChoice> {
+	id_choices(bot_data.non_self_people(user))
+}
+
+fn owned_bills(bot_data: &BotData, user: &User) -> Vec<ApplicationCommandOptionChoice> {
+	id_choices(bot_data.owned_bills(user))
+}
+
+fn bills(bot_data: &BotData, _user: &User) -> Vec<ApplicationCommandOptionChoice> {
+	id_choices(bot_data.bills())
+}
+
+fn subscribed_bills(bot_data: &BotData, user: &User) -> Vec<ApplicationCommandOptionChoice> {
+	id_choices(bot_data.subscribed_bills(user))
+}
+
+/// The parties, with their name as the value
+fn parties(bot_data: &BotData, _user: &User) -> Vec<ApplicationCommandOptionChoice> {
+	bot_data
+		.parties()
+		.map(|name| name.chars().take(100).collect::<String>())
+		.map(|value| {
+			ApplicationCommandOptionChoice::new()
+				.with_name(value.to_string())
+				.with_value(OptionType::String(value))
+		})
+		.collect()
+}
+
+pub async fn create_commands(client: &mut DiscordClient, application_id: &str) {
+	// Guild commands update instantly, unlike global ones
+	match Command::commands().sync(client, application_id, Some(DiscordClient::GUILD_ID)).await {
+		Ok(diff) => info!("Synced guild commands: {diff}"),
+		Err(e) => error!("Failed to create commands {e:?}"),
+	}
+	// Remove the commands from when they were registered globally, so they don't show up twice
+	match ApplicationCommandList::new().sync(client, application_id, None).await {
+		Ok(diff) => info!("Synced global commands: {diff}"),
+		Err(e) => error!("Failed to remove global commands {e:?}"),
+	}
 }
@@ -2,21 +2,47 @@ use crate::bot_data::*;
 use crate::general_commands::PRESIDENT_ROLL;
 use crate::utilities::*;
 use discord::*;
+
+/// Handles the `/decree` command by opening a modal to write the decree in
 pub async fn decree(handler_data: &mut HandlerData<'_>) {
-	let rolls = GuildMember::get_get_guild_member(handler_data.client, DiscordClient::GUILD_ID, &handler_data.user.id).await;
-	let is_president = rolls.map_or(false, |user| user.roles.contains(&PRESIDENT_ROLL.to_string()));
-	if !is_president {
-		respond_with_disappear_embed(
-			handler_data,
-			Embed::standard()
-				.with_title("Cannot decree")
-				.with_description("seul le président peut décréter et tu n'es pas le président"),
+	if !check_president(handler_data).await {
+		return;
+	}
+	let title = TextInput::new()
+		.with_custom_id("title")
+		.with_style(TextInputStyle::Short)
+		.with_label("Title")
+		.with_max_length(200)
+		.with_required(false);
+	let description = TextInput::new()
+		.with_custom_id("description")
+		.with_style(TextInputStyle::Paragraph)
+		.with_label("Description")
+		.with_max_length(4000);
+	let modal = Modal::new()
+		.with_custom_id(CustomId::new("decree", ""))
+		.with_title("Decree")
+		.with_components(ActionRows::new().with_components(title))
+		.with_components(ActionRows::new().with_components(description));
+
+	let response = InteractionCallback::new(InteractionResponse::Modal { data: modal })
+		.post_respond(
+			handler_data.client,
+			handler_data.interaction.id.clone(),
+			handler_data.interaction.token.clone(),
 		)
 		.await;
+	if let Err(e) = response {
+		error!("Failed to open decree modal {e:?}");
+	}
+}
+
+/// Handles submitting the decree modal
+pub async fn decree_submit(handler_data: &mut HandlerData<'_>) {
+	if !check_president(handler_data).await {
 		return;
 	}
-	let [title, description] =
-		["title", "description"].map(|text| handler_data.options.get(text).map(|option| option.as_str()).filter(|x| !x.is_empty()));
+	let [title, description] = ["title", "description"].map(|text| handler_data.options.string(text).ok().filter(|x| !x.is_empty()));
 
 	let n = handler_data.bot_data.decree;
 	let title = title.map_or_else(|| format!("Untitled decree #{n}"), |t| format!("Decree #{n} - {t}"));
@@ -41,3 +67,19 @@ pub async fn decree(handler_data: &mut HandlerData<'_>) {
 	.await;
 	handler_data.bot_data.decree += 1;
 }
+
+/// Checks that the user is the president, responding with an error if not
+async fn check_president(handler_data: &mut HandlerData<'_>) -> bool {
+	let rolls = handler_data.client.guild_member(DiscordClient::GUILD_ID, &handler_data.user.id).await;
+	let is_president = rolls.is_ok_and(|user| user.roles.contains(&PRESIDENT_ROLL.to_string()));
+	if !is_president {
+		respond_with_disappear_embed(
+			handler_data,
+			Embed::standard()
+				.with_title("Cannot decree")
+				.with_description("seul le président peut décréter et tu n'es pas le président"),
+		)
+		.await;
+	}
+	is_president
+}
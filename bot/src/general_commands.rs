@@ -1,6 +1,6 @@
 use crate::bot_data::*;
+use crate::create_commands::*;
 use crate::utilities::*;
-use crate::CheeseCoinTy;
 use chrono::Datelike;
 use discord::*;
 
@@ -10,59 +10,43 @@ pub const ELECTION_ADMIN_ROLE: &str = "1293607752534593576";
 pub const PRESIDENT_ROLL: &str = "907660552938061834";
 /// Handles the `/about` command
 pub async fn about<'a>(handler_data: &mut HandlerData<'a>) {
-	respond_with_embed(
-		handler_data,
-		Embed::standard()
-			.with_title("About")
-			.with_description("This bot is developed by Go Consulting Ltd. to handle the finances of New New Cheeseland."),
-	)
-	.await;
+	let latency = handler_data
+		.bot_data
+		.gateway_latency
+		.map_or_else(|| "unknown".to_string(), |latency| format!("{}ms", latency.as_millis()));
+	let description =
+		format!("This bot is developed by Go Consulting Ltd. to handle the finances of New New Cheeseland.\n\nGateway latency: {latency}");
+	respond_with_embed(handler_data, Embed::standard().with_title("About").with_description(description)).await;
 }
 
 /// Handles the `/balances` command
 pub async fn balances<'a>(handler_data: &mut HandlerData<'a>) {
-	fn format_account(Account { name, balance, .. }: &Account) -> String {
-		format!("{:-20} {}\n", format!("{}:", name), format_cheesecoin(*balance))
-	}
-
-	let mut description = format!(
-		"**Currency information**\n```\n{:-20} {}\n",
-		"Total Currency:",
-		format_cheesecoin(handler_data.bot_data.total_currency()),
+	let bot_data = &handler_data.bot_data;
+	let mut embed = Embed::standard()
+		.with_title("Balances")
+		.with_field("Total Currency", format_cheesecoin(bot_data.total_currency()), true)
+		.with_field("VAT", format!("{:.2}%", bot_data.vat), true)
+		.with_field("Balance Tax", format_wealth_tax(bot_data), false);
+
+	// Their personal account followed by their organisations
+	let cheese_user = bot_data.cheese_user(&handler_data.user);
+	let accounts = [&bot_data.accounts.personal_accounts[&cheese_user.account]].into_iter().chain(
+		cheese_user
+			.organisations
+			.iter()
+			.map(|account| &bot_data.accounts.organisation_accounts[account]),
 	);
-
-	for &(amount, tax_rate) in &handler_data.bot_data.wealth_tax {
-		let limit = if amount == u32::MAX as CheeseCoinTy || amount == CheeseCoinTy::MAX {
-			" (no limit)".to_string()
-		} else {
-			format!(" <{}", format_cheesecoin(amount))
-		};
-		let _ = write!(&mut description, "{:-20} {:.2}%\n", format!("Balance Tax{}:", limit), tax_rate);
-	}
-
-	let _ = write!(&mut description, "{:-20} {:.2}%\n", "VAT", handler_data.bot_data.vat);
-	//let _ = write!(&mut description, "{:-20} {}\n", "Tax", "removed via decree");
-	let _ = write!(&mut description, "```\n**Your accounts**\n```");
-
-	let cheese_user = handler_data.bot_data.cheese_user(&handler_data.user);
-
-	// Add their personal account to the resulting string
-	description += &format_account(&handler_data.bot_data.accounts.personal_accounts[&cheese_user.account]);
-
-	// Add their organisations to the resulting string
-	for account in &cheese_user.organisations {
-		description += &format_account(&handler_data.bot_data.accounts.organisation_accounts[&account])
+	for Account { name, balance, .. } in accounts {
+		embed = embed.with_field(name, format_cheesecoin(*balance), true);
 	}
 
-	description += "```";
-
-	respond_with_embed(handler_data, Embed::standard().with_title("Balances").with_description(description)).await;
+	respond_with_embed(handler_data, embed).await;
 }
 
 /// Handles the `/pay` command
-pub async fn pay<'a>(handler_data: &mut HandlerData<'a>) {
+pub async fn pay<'a>(handler_data: &mut HandlerData<'a>, command: Pay) {
 	let bot_data = &mut handler_data.bot_data;
-	let recipient = match account_option(bot_data, &handler_data.options["recipient"], BotData::account_exists, &handler_data.user).await {
+	let recipient = match account_option(bot_data, &command.recipient, BotData::account_exists, &handler_data.user).await {
 		Some(x) => x,
 		None => {
 			respond_with_embed(
@@ -73,16 +57,16 @@ pub async fn pay<'a>(handler_data: &mut HandlerData<'a>) {
 			return;
 		}
 	};
-	let from = match account_option(bot_data, &handler_data.options["from"], BotData::account_owned, &handler_data.user).await {
+	let from = match account_option(bot_data, &command.from, BotData::account_owned, &handler_data.user).await {
 		Some(x) => x,
 		None => {
 			respond_with_embed(handler_data, Embed::standard().with_title("Payment").with_description("Invalid from.")).await;
 			return;
 		}
 	};
-	let amount = handler_data.options["cheesecoin"].as_float();
+	let amount = command.cheesecoin;
 
-	let (payer_message, recipient_message) = transact(handler_data, recipient, from, amount);
+	let (mut payer_message, recipient_message) = transact(handler_data, recipient, from, amount);
 
 	if let Some(message) = recipient_message {
 		if let Some(recipient) = handler_data.bot_data.users.account_owner(recipient) {
@@ -93,7 +77,10 @@ pub async fn pay<'a>(handler_data: &mut HandlerData<'a>) {
 			)
 			.await
 			{
-				warn!("Payment dm failed {e:?}");
+				log_dm_error(&e, format_args!("Payment dm failed"));
+				if e.is_dm_closed() {
+					payer_message += " The recipient could not be notified as they do not accept direct messages.";
+				}
 			}
 		}
 	}
@@ -102,13 +89,11 @@ pub async fn pay<'a>(handler_data: &mut HandlerData<'a>) {
 }
 
 /// Handles the `/sudo print cheesecoin` command
-pub async fn print_money<'a>(handler_data: &mut HandlerData<'a>) {
+pub async fn print_money<'a>(handler_data: &mut HandlerData<'a>, command: PrintCheesecoin) {
 	let bot_data = &mut handler_data.bot_data;
 
-	let rolls = GuildMember::get_get_guild_member(handler_data.client, DiscordClient::GUILD_ID, &handler_data.user.id).await;
-	let is_valid = rolls
-		.as_ref()
-		.map_or(false, |user| user.roles.contains(&"1293607752534593576".to_string()));
+	let rolls = handler_data.client.guild_member(DiscordClient::GUILD_ID, &handler_data.user.id).await;
+	let is_valid = rolls.as_ref().is_ok_and(|user| user.roles.contains(&"1293607752534593576".to_string()));
 
 	if !is_valid {
 		respond_with_embed(
@@ -121,7 +106,7 @@ pub async fn print_money<'a>(handler_data: &mut HandlerData<'a>) {
 		return;
 	}
 
-	let recipient = match account_option(bot_data, &handler_data.options["recipient"], BotData::account_exists, &handler_data.user).await {
+	let recipient = match account_option(bot_data, &command.recipient, BotData::account_exists, &handler_data.user).await {
 		Some(x) => x,
 		None => {
 			respond_with_embed(
@@ -132,7 +117,7 @@ pub async fn print_money<'a>(handler_data: &mut HandlerData<'a>) {
 			return;
 		}
 	};
-	let amount = handler_data.options["cheesecoin"].as_float();
+	let amount = command.cheesecoin;
 
 	let (payer_message, recipient_message) = enact_print_money(handler_data, recipient, amount);
 
@@ -145,7 +130,7 @@ pub async fn print_money<'a>(handler_data: &mut HandlerData<'a>) {
 			)
 			.await
 			{
-				warn!("Print money dm failed {e:?}");
+				log_dm_error(&e, format_args!("Print money dm failed"));
 			}
 		}
 
@@ -165,8 +150,8 @@ pub async fn print_money<'a>(handler_data: &mut HandlerData<'a>) {
 
 /// Handles the `/claim rollcall` command
 pub async fn rollcall<'a>(handler_data: &mut HandlerData<'a>) {
-	let rolls = GuildMember::get_get_guild_member(handler_data.client, DiscordClient::GUILD_ID, &handler_data.user.id).await;
-	let is_citizen = rolls.as_ref().map_or(false, |user| user.roles.contains(&CITIZEN_ROLL.to_string()));
+	let rolls = handler_data.client.guild_member(DiscordClient::GUILD_ID, &handler_data.user.id).await;
+	let is_citizen = rolls.as_ref().is_ok_and(|user| user.roles.contains(&CITIZEN_ROLL.to_string()));
 	// let is_president = rolls.map_or(false, |user| user.roles.contains(&PRESIDENT_ROLL.to_string()));
 
 	if !is_citizen {
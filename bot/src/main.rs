@@ -1,9 +1,15 @@
 #![feature(int_roundings)]
 #![feature(panic_update_hook)]
 use std::panic;
+use std::sync::Arc;
 
 use chrono::Datelike;
-use discord::{async_channel::Sender, *};
+#[cfg(feature = "http")]
+use discord::interactions_server::InteractionServer;
+use discord::{
+	async_channel::{Receiver, Sender},
+	*,
+};
 
 mod bot_data;
 use bot_data::*;
@@ -47,96 +53,114 @@ fn init_logger() {
 
 async fn handle_interaction(interaction: Interaction, client: &mut DiscordClient, bot_data: &mut BotData) {
 	let command_type = interaction.interaction_type.clone();
+	let (id, token) = (interaction.id.clone(), interaction.token.clone());
 	let ConstructedData {
 		command,
 		focused,
 		mut handler_data,
-	} = construct_handler_data(interaction, client, bot_data);
+	} = match construct_handler_data(interaction, client, bot_data) {
+		Ok(constructed) => constructed,
+		Err(e) => {
+			error!("Invalid interaction {id}: {e}");
+			// Autocomplete can only be answered with choices
+			if matches!(command_type, InteractionType::ApplicationCommandAutocomplete) {
+				return;
+			}
+			let embed = Embed::standard()
+				.with_title("Invalid Interaction")
+				.with_description("Discord sent an interaction that could not be read, please try again.");
+			let message = ChannelMessage::new().with_embeds(embed).with_flags(1_u32 << 6);
+			let response = InteractionCallback::new(InteractionResponse::ChannelMessageWithSource { data: message })
+				.post_respond(client, id, token)
+				.await;
+			if let Err(e) = response {
+				error!("Failed to respond to invalid interaction {e:?}");
+			}
+			return;
+		}
+	};
 	match command_type {
 		InteractionType::ApplicationCommand => {
-			match command.as_str() {
-				"about" => general_commands::about(&mut handler_data).await,
-				"balances" => general_commands::balances(&mut handler_data).await,
-				"pay" => general_commands::pay(&mut handler_data).await,
-				"organisation create" => organisation_commands::organisation_create(&mut handler_data).await,
-				"organisation transfer" => organisation_commands::organisation_transfer(&mut handler_data).await,
-				"organisation rename" => organisation_commands::organisation_rename(&mut handler_data).await,
-				"organisation delete" => organisation_commands::organisation_delete(&mut handler_data).await,
-				"claim rollcall" => general_commands::rollcall(&mut handler_data).await,
-				"parliament add party" => parliament_commands::set_running(&mut handler_data, "Parliament Add Party", true).await,
-				"parliament delete party" => parliament_commands::set_running(&mut handler_data, "Parliament Delete Party", false).await,
-				"parliament vote" => parliament_commands::vote(&mut handler_data).await,
-				"parliament view results" => parliament_commands::view_results(&mut handler_data).await,
-				"parliament count results" => parliament_commands::count_results(&mut handler_data).await,
-				"bill create" => bill_commands::create_bill(&mut handler_data).await,
-				"bill delete" => bill_commands::bill_delete(&mut handler_data).await,
-				"bill subscribe" => bill_commands::bill_subscribe(&mut handler_data).await,
-				"bill unsubscribe" => bill_commands::bill_unsubscribe(&mut handler_data).await,
-				"bill view" => bill_commands::bill_view(&mut handler_data).await,
-				"role assign" => role_commands::role_assign(&mut handler_data).await,
-				"decree" => decree::decree(&mut handler_data).await,
-				"sudo print cheesecoin" => general_commands::print_money(&mut handler_data).await,
-				_ => warn!("Unhandled command {}", command),
+			let path = command.split(' ').collect::<Vec<_>>();
+			let Some(command) = read_option(&mut handler_data, |options| Command::parse(&path, options)).await else {
+				return;
+			};
+			match command {
+				Command::About(_) => general_commands::about(&mut handler_data).await,
+				Command::Balances(_) => general_commands::balances(&mut handler_data).await,
+				Command::Pay(command) => general_commands::pay(&mut handler_data, command).await,
+				Command::Organisation(Organisation::Create(command)) => organisation_commands::organisation_create(&mut handler_data, command).await,
+				Command::Organisation(Organisation::Transfer(command)) => {
+					organisation_commands::organisation_transfer(&mut handler_data, command).await
+				}
+				Command::Organisation(Organisation::Rename(command)) => organisation_commands::organisation_rename(&mut handler_data, command).await,
+				Command::Organisation(Organisation::Delete(command)) => organisation_commands::organisation_delete(&mut handler_data, command).await,
+				Command::Claim(Claim::Rollcall) => general_commands::rollcall(&mut handler_data).await,
+				Command::Parliament(Parliament::Add(ParliamentAdd::Party(command))) => {
+					parliament_commands::set_running(&mut handler_data, "Parliament Add Party", true, command.party).await
+				}
+				Command::Parliament(Parliament::Delete(ParliamentDelete::Party(command))) => {
+					parliament_commands::set_running(&mut handler_data, "Parliament Delete Party", false, command.party).await
+				}
+				Command::Parliament(Parliament::Vote(command)) => parliament_commands::vote(&mut handler_data, command).await,
+				Command::Parliament(Parliament::View(ParliamentView::Results)) => parliament_commands::view_results(&mut handler_data).await,
+				Command::Sudo(Sudo::Count(SudoCount::Results)) => parliament_commands::count_results(&mut handler_data).await,
+				Command::Bill(BillCommand::Create(command)) => bill_commands::create_bill(&mut handler_data, command).await,
+				Command::Bill(BillCommand::Delete(command)) => bill_commands::bill_delete(&mut handler_data, command).await,
+				Command::Bill(BillCommand::Subscribe(command)) => bill_commands::bill_subscribe(&mut handler_data, command).await,
+				Command::Bill(BillCommand::Unsubscribe(command)) => bill_commands::bill_unsubscribe(&mut handler_data, command).await,
+				Command::Bill(BillCommand::View) => bill_commands::bill_view(&mut handler_data).await,
+				Command::Role(RoleCommand::Assign(command)) => role_commands::role_assign(&mut handler_data, command).await,
+				Command::Decree(_) => decree::decree(&mut handler_data).await,
+				Command::Sudo(Sudo::Print(SudoPrint::Cheesecoin(command))) => general_commands::print_money(&mut handler_data, command).await,
 			};
 		}
+		InteractionType::MessageComponent => match command.as_str() {
+			"vote" => parliament_commands::vote_component(&mut handler_data).await,
+			legacy if legacy.starts_with("vote") => parliament_commands::legacy_vote_button(&mut handler_data).await,
+			_ => {
+				warn!("Unhandled component {}", command);
+				respond_with_disappear_embed(
+					&mut handler_data,
+					Embed::standard()
+						.with_title("Expired")
+						.with_description("This component is no longer in use."),
+				)
+				.await;
+			}
+		},
+		InteractionType::ModalSubmit => match command.as_str() {
+			"decree" => decree::decree_submit(&mut handler_data).await,
+			_ => warn!("Unhandled modal {}", command),
+		},
 		InteractionType::ApplicationCommandAutocomplete => {
-			let InteractionDataOption { name, value, .. } = focused.unwrap();
-			let str_value = value.as_ref().unwrap().as_str().to_lowercase();
+			let Some(InteractionDataOption { name, value, .. }) = focused else {
+				warn!(r#"Autocomplete on command "{}" without a focused option"#, command);
+				return;
+			};
+			let str_value = value.as_ref().and_then(OptionType::as_str).unwrap_or_default().to_lowercase();
 			info!("Autocomplete focused {} command {} value {}", name, command, str_value);
 
-			let from_id_str = |(name, id): (String, u64)| {
-				ApplicationCommandOptionChoice::new()
-					.with_name(&name[name.len().saturating_sub(100)..])
-					.with_value(OptionType::String(id.to_string()))
-			};
-			let choices = match (command.as_str(), name.as_str()) {
-				("pay", "recipient") | ("sudo print cheesecoin", "recipient") => handler_data
-					.bot_data
-					.personal_accounts()
-					.chain(handler_data.bot_data.organisation_accounts())
-					.map(from_id_str)
-					.collect::<Vec<_>>(),
-				("pay", "from") | ("bill subscribe", "from") | ("bill create", "to") => handler_data
-					.bot_data
-					.personal_account(&handler_data.user)
-					.chain(handler_data.bot_data.owned_orgs(&handler_data.user))
-					.map(from_id_str)
-					.collect(),
-				("organisation transfer", "name") | ("organisation rename", "name") | ("organisation delete", "name") => {
-					handler_data.bot_data.owned_orgs(&handler_data.user).map(from_id_str).collect()
-				}
-				("organisation transfer", "owner") => handler_data.bot_data.non_self_people(&handler_data.user).map(from_id_str).collect(),
-				("bill delete", "name") => handler_data.bot_data.owned_bills(&handler_data.user).map(from_id_str).collect(),
-				("bill subscribe", "name") => handler_data.bot_data.bills().map(from_id_str).collect(),
-				("bill unsubscribe", "name") => handler_data.bot_data.subscribed_bills(&handler_data.user).map(from_id_str).collect(),
-				("parliament add party" | "parliament delete party" | "parliament vote", "party") => handler_data
-					.bot_data
-					.parties()
-					.map(|name| name[name.len().saturating_sub(100)..].to_string())
-					.map(|value| {
-						ApplicationCommandOptionChoice::new()
-							.with_name(value.to_string())
-							.with_value(OptionType::String(value))
-					})
-					.collect(),
-				_ => {
-					warn!(r#"Invalid autocomplete for "{}" on command "{}""#, command, name);
-					return;
-				}
+			let path = command.split(' ').collect::<Vec<_>>();
+			let Some(provider) = <Command as CommandAutocomplete<Choices>>::autocomplete(&path, &name) else {
+				warn!(r#"Invalid autocomplete for "{}" on command "{}""#, name, command);
+				return;
 			};
 
-			let choices = choices
+			let choices = provider(handler_data.bot_data, &handler_data.user)
 				.into_iter()
 				.filter(|option| option.name.to_lowercase().contains(&str_value))
 				.take(25)
 				.collect::<Vec<_>>();
 
-			InteractionCallback::new(InteractionResponse::ApplicationCommandAutocompleteResult {
+			let response = InteractionCallback::new(InteractionResponse::ApplicationCommandAutocompleteResult {
 				data: AutocompleteResult { choices },
 			})
 			.post_respond(handler_data.client, handler_data.interaction.id, handler_data.interaction.token)
-			.await
-			.unwrap();
+			.await;
+			if let Err(e) = response {
+				error!("Failed to respond to autocomplete {e:?}");
+			}
 		}
 		_ => warn!("received interaction of type {:?} which was not handled", command_type),
 	}
@@ -144,41 +168,27 @@ async fn handle_interaction(interaction: Interaction, client: &mut DiscordClient
 }
 
 #[derive(Clone)]
+#[allow(clippy::large_enum_variant)]
 enum MainMessage {
-	Gateway(GatewayRecieve),
-	GatewayClosed,
-	Heartbeat,
+	Dispatch(ShardEvent),
+	/// An interaction received over http
+	#[cfg(feature = "http")]
+	Interaction(Interaction),
 	WealthTax,
 	CheckElection,
-}
-
-async fn read_websocket(mut read: Read, send_ev: Sender<MainMessage>) {
-	while let Some(Ok(Message::Text(text))) = read.next().await {
-		debug!("received text {}", text);
-		match serde_json::from_str(&text) {
-			Ok(deserialised) => {
-				if send_ev.send(MainMessage::Gateway(deserialised)).await.is_err() {
-					return;
-				}
-			}
-			Err(e) => {
-				error!("Error decoding gateway message {:?}", e);
-			}
-		}
-	}
-	warn!("Websocket closing!");
-	send_ev.send(MainMessage::GatewayClosed).await.unwrap_or(())
+	RefreshPresence,
 }
 
 #[test]
 fn decode_gateway() {
-	let x:GatewayRecieve = serde_json::from_str(r##"{"t":"INTERACTION_CREATE","s":36,"op":0,"d":{"version":1,"user":{"username":"Käse","public_flags":128,"id":"630073509137350690","display_name":null,"discriminator":"3615","avatar_decoration":null,"avatar":"761226738b7c90394e301b7f387fdc9d"},"type":3,"token":"aW50ZXJhY3Rpb246MTA3ODA2ODQ5Mzc4Nzg1NjkzODplVkhFUnZweEZNYkxqWGI2ZmdRVHNJUUY2UUxrZHViM3RMbWl1SVExNWhLUlAxQTNvQ0dKRDBBMnhiUmFVc29RZFI5RnAwUG4xTTRzVEROYzdnam5USnRKUUc2cm1LMkZNVnJtZmY5S1NTQ0tKVGhHN1lhU1V1VTVSdEpmQ2xvMg","message":{"webhook_id":"910254320740610069","type":20,"tts":false,"timestamp":"2023-02-22T21:39:17.323000+00:00","pinned":false,"mentions":[],"mention_roles":[],"mention_everyone":false,"interaction":{"user":{"username":"Käse","public_flags":128,"id":"630073509137350690","display_name":null,"discriminator":"3615","avatar_decoration":null,"avatar":"761226738b7c90394e301b7f387fdc9d"},"type":2,"name":"parliament vote","id":"1078068487651606608"},"id":"1078068489006358578","flags":0,"embeds":[],"edited_timestamp":null,"content":"Vote for your candidate:","components":[{"type":1,"components":[{"type":2,"style":2,"label":"Alan","custom_id":"vote887020108696920116"},{"type":2,"style":2,"label":"аe","custom_id":"vote762325231925854231"},{"type":2,"style":2,"label":"Elliot F.","custom_id":"vote722468356711776269"}]}],"channel_id":"910597009466093628","author":{"username":"Cheese Bot","public_flags":0,"id":"910254320740610069","display_name":null,"discriminator":"4538","bot":true,"avatar_decoration":null,"avatar":null},"attachments":[],"application_id":"910254320740610069"},"locale":"en-GB","id":"1078068493787856938","data":{"custom_id":"vote887020108696920116","component_type":2},"channel_id":"910597009466093628","application_id":"910254320740610069"}}"##).unwrap();
-	let x:GatewayRecieve = serde_json::from_str(r##"{"t":"INTERACTION_CREATE","s":32,"op":0,"d":{"version":1,"type":3,"token":"aW50ZXJhY3Rpb246MTA3ODA2MzY2OTg3Njg4NzY1Mjo2RDRLVUg2c1FvSTlSa2w0Q2VkR3NMelhiN2pXMUpDMzNWT2VMeTlDZzEyZWUyWWRNc2JWd1psdWJsOE5GZzBCTGZnYUM0OXEzZ1E1eHJxZFBEb3ZDY1o5RTlBa2RHNzJyUGZ0ejVaeHd5R1dpQ0lXYVNpWFVuUVBoaEo5eDY2NA","message":{"webhook_id":"910254320740610069","type":20,"tts":false,"timestamp":"2023-02-22T21:11:38.400000+00:00","pinned":false,"mentions":[],"mention_roles":[],"mention_everyone":false,"interaction":{"user":{"username":"Käse","public_flags":128,"id":"630073509137350690","display_name":null,"discriminator":"3615","avatar_decoration":null,"avatar":"761226738b7c90394e301b7f387fdc9d"},"type":2,"name":"parliament vote","id":"1078061529682956439"},"id":"1078061530978992240","flags":0,"embeds":[],"edited_timestamp":null,"content":"Vote for your candidate:","components":[{"type":1,"components":[{"type":2,"style":2,"label":"Alan","custom_id":"vote887020108696920116"},{"type":2,"style":2,"label":"аe","custom_id":"vote762325231925854231"},{"type":2,"style":2,"label":"Elliot F.","custom_id":"vote722468356711776269"}]}],"channel_id":"956233767767408741","author":{"username":"Cheese Bot","public_flags":0,"id":"910254320740610069","display_name":null,"discriminator":"4538","bot":true,"avatar_decoration":null,"avatar":null},"attachments":[],"application_id":"910254320740610069"},"member":{"user":{"username":"18XYang","public_flags":0,"id":"764206694841974795","display_name":null,"discriminator":"6684","avatar_decoration":null,"avatar":null},"roles":["1078035383511699496","985630968650010705","985804444237172797"],"premium_since":null,"permissions":"539018919105","pending":false,"nick":"Xiao-Kun","mute":false,"joined_at":"2023-02-22T19:00:46.675000+00:00","is_pending":false,"flags":0,"deaf":false,"communication_disabled_until":null,"avatar":null},"locale":"en-GB","id":"1078063669876887652","guild_locale":"en-US","guild_id":"907657508292792342","entitlement_sku_ids":[],"data":{"custom_id":"vote887020108696920116","component_type":2},"channel_id":"956233767767408741","application_id":"910254320740610069","app_permissions":"4398046511103"}}"##).unwrap();
+	let _x:GatewayRecieve = serde_json::from_str(r##"{"t":"INTERACTION_CREATE","s":36,"op":0,"d":{"version":1,"user":{"username":"Käse","public_flags":128,"id":"630073509137350690","display_name":null,"discriminator":"3615","avatar_decoration":null,"avatar":"761226738b7c90394e301b7f387fdc9d"},"type":3,"token":"aW50ZXJhY3Rpb246MTA3ODA2ODQ5Mzc4Nzg1NjkzODplVkhFUnZweEZNYkxqWGI2ZmdRVHNJUUY2UUxrZHViM3RMbWl1SVExNWhLUlAxQTNvQ0dKRDBBMnhiUmFVc29RZFI5RnAwUG4xTTRzVEROYzdnam5USnRKUUc2cm1LMkZNVnJtZmY5S1NTQ0tKVGhHN1lhU1V1VTVSdEpmQ2xvMg","message":{"webhook_id":"910254320740610069","type":20,"tts":false,"timestamp":"2023-02-22T21:39:17.323000+00:00","pinned":false,"mentions":[],"mention_roles":[],"mention_everyone":false,"interaction":{"user":{"username":"Käse","public_flags":128,"id":"630073509137350690","display_name":null,"discriminator":"3615","avatar_decoration":null,"avatar":"761226738b7c90394e301b7f387fdc9d"},"type":2,"name":"parliament vote","id":"1078068487651606608"},"id":"1078068489006358578","flags":0,"embeds":[],"edited_timestamp":null,"content":"Vote for your candidate:","components":[{"type":1,"components":[{"type":2,"style":2,"label":"Alan","custom_id":"vote887020108696920116"},{"type":2,"style":2,"label":"аe","custom_id":"vote762325231925854231"},{"type":2,"style":2,"label":"Elliot F.","custom_id":"vote722468356711776269"}]}],"channel_id":"910597009466093628","author":{"username":"Cheese Bot","public_flags":0,"id":"910254320740610069","display_name":null,"discriminator":"4538","bot":true,"avatar_decoration":null,"avatar":null},"attachments":[],"application_id":"910254320740610069"},"locale":"en-GB","id":"1078068493787856938","data":{"custom_id":"vote887020108696920116","component_type":2},"channel_id":"910597009466093628","application_id":"910254320740610069"}}"##).unwrap();
+	let _x:GatewayRecieve = serde_json::from_str(r##"{"t":"INTERACTION_CREATE","s":32,"op":0,"d":{"version":1,"type":3,"token":"aW50ZXJhY3Rpb246MTA3ODA2MzY2OTg3Njg4NzY1Mjo2RDRLVUg2c1FvSTlSa2w0Q2VkR3NMelhiN2pXMUpDMzNWT2VMeTlDZzEyZWUyWWRNc2JWd1psdWJsOE5GZzBCTGZnYUM0OXEzZ1E1eHJxZFBEb3ZDY1o5RTlBa2RHNzJyUGZ0ejVaeHd5R1dpQ0lXYVNpWFVuUVBoaEo5eDY2NA","message":{"webhook_id":"910254320740610069","type":20,"tts":false,"timestamp":"2023-02-22T21:11:38.400000+00:00","pinned":false,"mentions":[],"mention_roles":[],"mention_everyone":false,"interaction":{"user":{"username":"Käse","public_flags":128,"id":"630073509137350690","display_name":null,"discriminator":"3615","avatar_decoration":null,"avatar":"761226738b7c90394e301b7f387fdc9d"},"type":2,"name":"parliament vote","id":"1078061529682956439"},"id":"1078061530978992240","flags":0,"embeds":[],"edited_timestamp":null,"content":"Vote for your candidate:","components":[{"type":1,"components":[{"type":2,"style":2,"label":"Alan","custom_id":"vote887020108696920116"},{"type":2,"style":2,"label":"аe","custom_id":"vote762325231925854231"},{"type":2,"style":2,"label":"Elliot F.","custom_id":"vote722468356711776269"}]}],"channel_id":"956233767767408741","author":{"username":"Cheese Bot","public_flags":0,"id":"910254320740610069","display_name":null,"discriminator":"4538","bot":true,"avatar_decoration":null,"avatar":null},"attachments":[],"application_id":"910254320740610069"},"member":{"user":{"username":"18XYang","public_flags":0,"id":"764206694841974795","display_name":null,"discriminator":"6684","avatar_decoration":null,"avatar":null},"roles":["1078035383511699496","985630968650010705","985804444237172797"],"premium_since":null,"permissions":"539018919105","pending":false,"nick":"Xiao-Kun","mute":false,"joined_at":"2023-02-22T19:00:46.675000+00:00","is_pending":false,"flags":0,"deaf":false,"communication_disabled_until":null,"avatar":null},"locale":"en-GB","id":"1078063669876887652","guild_locale":"en-US","guild_id":"907657508292792342","entitlement_sku_ids":[],"data":{"custom_id":"vote887020108696920116","component_type":2},"channel_id":"956233767767408741","application_id":"910254320740610069","app_permissions":"4398046511103"}}"##).unwrap();
 }
 
-/// Sends a message every `period` milliseconds
-async fn dispatch_msg(send_ev: Sender<MainMessage>, interval: u64, msg: MainMessage) {
-	let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(interval));
+/// Sends a message every `period` milliseconds, starting after `delay`
+async fn dispatch_msg(send_ev: Sender<MainMessage>, delay: tokio::time::Duration, interval: u64, msg: MainMessage) {
+	let start = tokio::time::Instant::now() + delay;
+	let mut interval = tokio::time::interval_at(start, tokio::time::Duration::from_millis(interval));
 	loop {
 		interval.tick().await;
 		if send_ev.send(msg.clone()).await.is_err() {
@@ -188,33 +198,44 @@ async fn dispatch_msg(send_ev: Sender<MainMessage>, interval: u64, msg: MainMess
 	}
 }
 
-/// Continually tries to reconnect
-async fn run_loop() {
-	let mut client = DiscordClient::new(include_str!("token.txt").trim());
-
-	// Open file and deserialise the data.
-	let path = "cheese_data.ron";
+/// Opens the file (after backing it up) and deserialises the data
+fn load_bot_data(path: &str) -> BotData {
 	std::fs::copy(path, format!("cheese_data_back_{}.ron", chrono::Utc::now().num_days_from_ce())).unwrap();
-	let mut bot_data = std::fs::read_to_string(path).map_or(BotData::default(), |v| match ron::from_str(&v) {
+	std::fs::read_to_string(path).map_or(BotData::default(), |v| match ron::from_str(&v) {
 		Err(e) => {
 			error!("Decoding ron {:?}", e);
 			panic!("Error decoding ron")
 		}
 		Ok(x) => x,
-	});
+	})
+}
 
-	loop {
-		warn!("Running in run loop");
-		run(&mut client, &mut bot_data, path).await;
+/// Runs the bot until the gateway stops, retrying if it can not be reached
+async fn run_loop() {
+	let mut client = DiscordClient::new(include_str!("token.txt").trim()).with_cache().with_compression();
+
+	let path = "cheese_data.ron";
+	let mut bot_data = load_bot_data(path);
+
+	while let Err(e) = run(&mut client, &mut bot_data, path).await {
+		error!("Failed to start the gateway {e:?}, retrying");
+		tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
 	}
+	// The shards only stop on close codes which reconnecting will not fix
+	error!("The gateway has stopped, check the token and that the Server Members intent is enabled in the developer portal");
+}
+
+/// Checks if a week has passed since the last balance tax
+fn wealth_tax_due(bot_data: &BotData) -> bool {
+	(chrono::Utc::now() - bot_data.last_wealth_tax) > chrono::Duration::hours(24 * 7 - 4)
 }
 
 async fn check_wealth_tax(bot_data: &mut BotData, client: &mut DiscordClient) {
-	if (chrono::Utc::now() - bot_data.last_wealth_tax) <= chrono::Duration::hours(24 * 7 - 4) {
+	if !wealth_tax_due(bot_data) {
 		return;
 	}
 
-	bot_data.last_wealth_tax = bot_data.last_wealth_tax + chrono::Duration::hours(24 * 7);
+	bot_data.last_wealth_tax += chrono::Duration::hours(24 * 7);
 	info!("Applying wealth tax.");
 
 	// Applies wealth tax to a specific account returning the log information for the user
@@ -235,7 +256,7 @@ async fn check_wealth_tax(bot_data: &mut BotData, client: &mut DiscordClient) {
 		Some((result, tax))
 	}
 
-	let users = (&bot_data).users.users.keys().into_iter().map(|x| x.clone()).collect::<Vec<_>>();
+	let users = bot_data.users.users.keys().cloned().collect::<Vec<_>>();
 	let mut total_tax = 0;
 
 	for user_id in users {
@@ -271,8 +292,8 @@ async fn check_wealth_tax(bot_data: &mut BotData, client: &mut DiscordClient) {
 
 		let mut result = format!("{:20} {:10} {}", "Account Name", "Tax", "New value");
 
-		let tax = &apply_wealth_tax_account(bot_data, bot_data.users.users[&user_id].account.clone(), Some("Personal"), tax_rate);
-		result += tax.as_ref().map_or(&"", |tax| &tax.0);
+		let tax = &apply_wealth_tax_account(bot_data, bot_data.users.users[&user_id].account, Some("Personal"), tax_rate);
+		result += tax.as_ref().map_or("", |tax| &tax.0);
 		total_tax += tax.as_ref().map_or(0, |tax| tax.1);
 
 		for org in bot_data.users.users[&user_id].organisations.clone() {
@@ -280,7 +301,7 @@ async fn check_wealth_tax(bot_data: &mut BotData, client: &mut DiscordClient) {
 				continue;
 			}
 			let tax = &apply_wealth_tax_account(bot_data, org, None, tax_rate);
-			result += tax.as_ref().map_or(&"", |tax| &tax.0);
+			result += tax.as_ref().map_or("", |tax| &tax.0);
 			total_tax += tax.as_ref().map_or(0, |tax| tax.1);
 		}
 
@@ -302,7 +323,7 @@ async fn check_wealth_tax(bot_data: &mut BotData, client: &mut DiscordClient) {
 			)
 			.await
 			{
-				error!("Failed to dm {user_id} about their wealth tax: {e:?}.")
+				log_dm_error(&e, format_args!("Failed to dm {user_id} about their wealth tax"));
 			}
 		}
 	}
@@ -318,7 +339,10 @@ async fn check_wealth_tax(bot_data: &mut BotData, client: &mut DiscordClient) {
 			)
 			.await
 			{
-				error!("Failed to message treasury owner {user_id} about the collected wealth tax: {e:?}");
+				log_dm_error(
+					&e,
+					format_args!("Failed to message treasury owner {user_id} about the collected wealth tax"),
+				);
 			};
 			break;
 		}
@@ -328,7 +352,7 @@ async fn check_wealth_tax(bot_data: &mut BotData, client: &mut DiscordClient) {
 }
 
 async fn check_bills(bot_data: &mut BotData, client: &mut DiscordClient) {
-	for (_bill_id, bill) in &bot_data.bills {
+	for bill in bot_data.bills.values() {
 		let mut bill_owner_result = String::new();
 		let mut bill_owner_total: CheeseCoinTy = 0;
 		let Some(bill_owner) = bot_data.accounts.account(bill.owner) else {
@@ -360,7 +384,7 @@ async fn check_bills(bot_data: &mut BotData, client: &mut DiscordClient) {
 							.with_title(format!("Paid {} for {} bill", format_cheesecoin(bill.amount), bill.name))
 							.with_description(sender_message);
 						if let Err(e) = dm_embed(client, embed, payer.clone()).await {
-							error!("Failed to dm bill payer {payer} about payment: {e:?}");
+							log_dm_error(&e, format_args!("Failed to dm bill payer {payer} about payment"));
 						}
 					}
 				} else {
@@ -382,7 +406,7 @@ async fn check_bills(bot_data: &mut BotData, client: &mut DiscordClient) {
 							))
 							.with_description(sender_message);
 						if let Err(e) = dm_embed(client, embed, payer.clone()).await {
-							error!("Failed to send failiure to transfer message to {payer}: {e:?}");
+							log_dm_error(&e, format_args!("Failed to send failiure to transfer message to {payer}"));
 						}
 					}
 				}
@@ -402,14 +426,14 @@ async fn check_bills(bot_data: &mut BotData, client: &mut DiscordClient) {
 						.get(&bill.owner)
 						.map_or("your personal account", |org| &org.name),
 					if bill_owner_result.is_empty() {
-						format!("None")
+						"None".to_string()
 					} else {
 						format!("{:20} {}{}", "Account Name", "Charge", bill_owner_result)
 					},
 				));
 			if let Some(recipient) = bot_data.users.account_owner(bill.owner) {
 				if let Err(e) = dm_embed(client, embed, recipient.clone()).await {
-					error!("Failed to send collected bill to {recipient} error: {e:?}");
+					log_dm_error(&e, format_args!("Failed to send collected bill to {recipient}"));
 				}
 			}
 			if let Some(owner) = bot_data.accounts.account_mut(bill.owner) {
@@ -425,130 +449,263 @@ async fn check_bills(bot_data: &mut BotData, client: &mut DiscordClient) {
 
 async fn treasury_balance(bot_data: &mut BotData, client: &mut DiscordClient) {
 	let balance = bot_data.treasury_account().balance;
-	let mut description = format!(
-		"**Financial information for {}**\n```\n{:-20} {}\n",
-		chrono::Utc::now().format("%d/%m/%Y"),
-		"Total Currency:",
-		format_cheesecoin(bot_data.total_currency()),
-	);
-	for &(amount, tax_rate) in &bot_data.wealth_tax {
-		let limit = if amount == u32::MAX as CheeseCoinTy || amount == CheeseCoinTy::MAX {
-			" (no limit)".to_string()
-		} else {
-			format!(" <{}", format_cheesecoin(amount))
-		};
-		let _ = write!(&mut description, "{:-20} {:.2}%\n", format!("Balance Tax{}:", limit), tax_rate);
-	}
-	let _ = write!(&mut description, "{:-20} {}\n```", "Treasury Balance:", format_cheesecoin(balance));
+	let embed = Embed::standard()
+		.with_title("Daily Treasury Report")
+		.with_description(format!("Financial information for {}", chrono::Utc::now().format("%d/%m/%Y")))
+		.with_field("Total Currency", format_cheesecoin(bot_data.total_currency()), true)
+		.with_field("Treasury Balance", format_cheesecoin(balance), true)
+		.with_field("Balance Tax", format_wealth_tax(bot_data), false);
 	bot_data.treasury_balances.push(balance);
 	bot_data.save();
-	let embed = Embed::standard().with_title("Daily Treasury Report").with_description(description);
 
-	if let Err(e) = ChannelMessage::new().with_embeds(embed).post_create(client, "1018447658685321266").await {
+	// The history of the treasury balance from each daily report
+	let mut csv = "report,treasury_balance\n".to_string();
+	for (report, &balance) in bot_data.treasury_balances.iter().enumerate() {
+		let _ = writeln!(csv, "{report},{:.2}", balance as f64 / 100.);
+	}
+	let files = [FileUpload::new("treasury_balances.csv", "text/csv", csv)];
+
+	let message = ChannelMessage::new().with_embeds(embed);
+	if let Err(e) = message.post_create_with_files(client, "1018447658685321266", &files).await {
 		error!("Failed to post treasury balance {e:?}");
 	};
 }
 
+#[allow(dead_code)]
 fn twaddle_id() -> String {
 	"762325231925854231".to_string()
 }
 async fn twaddle(_bot_data: &mut BotData, _client: &mut DiscordClient) {}
 
-/// Runs the bot
-async fn run(client: &mut DiscordClient, bot_data: &mut BotData, path: &str) {
-	bot_data.file_path = path.to_string();
-	let gateway = GatewayMeta::get_gateway_meta(client).await.unwrap();
-	info!("received gateway metadata: {:?}", gateway);
+/// Starts the regular economy tasks
+fn start_timers(send_ev: &Sender<MainMessage>) {
+	let hours = 3 * 60 * 60 * 1000;
+	tokio::spawn(dispatch_msg(send_ev.clone(), Default::default(), hours, MainMessage::WealthTax));
+	tokio::spawn(dispatch_msg(send_ev.clone(), Default::default(), hours, MainMessage::CheckElection));
+	// Checked every minute rather than after every payment, so that busy periods do not hit discord's rate limit
+	let presence = tokio::time::Duration::from_secs(60);
+	tokio::spawn(dispatch_msg(
+		send_ev.clone(),
+		presence,
+		presence.as_millis() as u64,
+		MainMessage::RefreshPresence,
+	));
+}
 
-	let (send_ev, recieve_ev) = async_channel::unbounded();
+/// The bot's status, which shows the treasury balance
+fn treasury_presence(bot_data: &BotData) -> PresenceUpdate {
+	let balance = format_cheesecoin(bot_data.treasury_account().balance);
+	PresenceUpdate::new().with_activities(Activity::custom(format!("Treasury: {balance}")))
+}
 
-	let Some(Connection { send_outgoing_message, read }) = client.connect_gateway(gateway.url).await else {
-		error!("Failed to connect");
-		return;
-	};
+/// Updates the bot's status, which can only be shown when connected to the gateway
+async fn update_presence(manager: Option<&ShardManager>, presence: PresenceUpdate) {
+	if let Some(manager) = manager {
+		manager.update_presence(presence).await;
+	}
+}
 
+/// Runs the bot on the gateway until every shard has stopped
+///
+/// `GUILD_MEMBERS` is a privileged intent, so it has to be enabled in the developer portal (Bot > Privileged Gateway Intents >
+/// Server Members Intent) or discord closes the gateway with code 4014.
+async fn run(client: &mut DiscordClient, bot_data: &mut BotData, path: &str) -> Result<(), NetError> {
+	bot_data.file_path = path.to_string();
+	let identify = Identify::new()
+		.with_intents(Intents::GUILDS | Intents::GUILD_MEMBERS)
+		.with_token(&client.token)
+		.with_properties(ConnectionProperties::new().with_device("Cheese"))
+		.with_presence(treasury_presence(bot_data));
+	let manager = Arc::new(ShardManager::start(client, identify).await?);
 	info!("Connected");
 
-	let mut sequence_number = None;
-
-	tokio::spawn(read_websocket(read, send_ev.clone()));
+	let (send_ev, recieve_ev) = async_channel::unbounded();
+	start_timers(&send_ev);
+	let shards = manager.clone();
+	tokio::spawn(async move {
+		while let Some(event) = shards.next().await {
+			if send_ev.send(MainMessage::Dispatch(event)).await.is_err() {
+				return;
+			}
+		}
+		// Every shard has stopped, so stop the timers as well
+		send_ev.close();
+	});
 
-	tokio::spawn(dispatch_msg(send_ev.clone(), 3 * 60 * 60 * 1000, MainMessage::WealthTax));
-	tokio::spawn(dispatch_msg(send_ev.clone(), 3 * 60 * 60 * 1000, MainMessage::CheckElection));
+	event_loop(client, bot_data, recieve_ev, Some(&manager)).await;
+	Ok(())
+}
 
+/// Handles the events (from the gateway or the http server) and the regular tasks until the channel is closed
+async fn event_loop(client: &mut DiscordClient, bot_data: &mut BotData, recieve_ev: Receiver<MainMessage>, manager: Option<&ShardManager>) {
+	// The treasury balance currently shown in the presence (which starts with the identify)
+	let mut presence_balance = bot_data.treasury_account().balance;
 	while let Ok(main_message) = recieve_ev.recv().await {
 		match main_message {
-			MainMessage::Gateway(deserialised) => match deserialised {
-				GatewayRecieve::Dispatch { d, s } => {
-					sequence_number = Some(s);
-
-					debug!("received dispatch {:?}", d);
-					match d {
-						Dispatch::Ready(r) => create_commands(client, &r.application.id).await,
-						Dispatch::InteractionCreate(interaction) => handle_interaction(interaction, client, bot_data).await,
-						_ => warn!("Unhandled dispatch"),
-					}
-				}
-				GatewayRecieve::Heartbeat { .. } => {
-					warn!("Discord wants a heartbeat, sending (should probably not happen)");
-					send_ev.send(MainMessage::Heartbeat).await.unwrap();
-				}
-				GatewayRecieve::Reconnect => {
-					warn!("Discord has told us to reconnect");
-					return;
+			MainMessage::Dispatch(ShardEvent { shard_id, dispatch }) => {
+				debug!("received dispatch {:?} on shard {}", dispatch, shard_id);
+				client.observe(&dispatch);
+				bot_data.gateway_latency = manager.and_then(|manager| manager.latency(shard_id));
+				match dispatch {
+					// Every shard is ready separately, but the commands only need creating once
+					Dispatch::Ready(r) if shard_id == 0 => create_commands(client, &r.application.id).await,
+					Dispatch::Ready(_) => {}
+					Dispatch::Resumed(_) => info!("Resumed session on shard {shard_id}"),
+					Dispatch::InteractionCreate(interaction) => handle_interaction(interaction, client, bot_data).await,
+					Dispatch::GuildMemberAdd(event) => info!("Member joined {}", event.member.user.username),
+					Dispatch::GuildMemberRemove(event) => info!("Member left {}", event.user.username),
+					// Only kept up to date in the cache by `client.observe`
+					Dispatch::GuildCreate(_)
+					| Dispatch::GuildMemberUpdate(_)
+					| Dispatch::GuildRoleCreate(_)
+					| Dispatch::GuildRoleUpdate(_)
+					| Dispatch::GuildRoleDelete(_)
+					| Dispatch::ChannelCreate(_)
+					| Dispatch::ChannelDelete(_)
+					| Dispatch::MessageCreate(_) => {}
+					Dispatch::Unknown(unknown) => debug!("Unknown dispatch {}", unknown.t),
 				}
-				GatewayRecieve::InvalidSession { d } => error!("Invalid session, can reconnect {}", d),
-				GatewayRecieve::Hello { d } => {
-					let identify = GatewaySend::Identify {
-						d: Identify::new()
-							.with_intents(INTENTS_NONE)
-							.with_token(&client.token)
-							.with_properties(ConnectionProperties::new().with_device("Cheese")),
-					};
-
-					send_outgoing_message.send(serde_json::to_string(&identify).unwrap()).await.unwrap();
-					tokio::spawn(dispatch_msg(send_ev.clone(), d.heartbeat_interval, MainMessage::Heartbeat));
-				}
-				GatewayRecieve::HeartbeatACK => {}
-			},
-			MainMessage::GatewayClosed => return,
-			MainMessage::Heartbeat => {
-				send_outgoing_message
-					.send(serde_json::to_string(&GatewaySend::Heartbeat { d: sequence_number }).unwrap())
-					.await
-					.unwrap();
 			}
 			MainMessage::WealthTax => {
-				check_wealth_tax(bot_data, client).await;
-
-				let day = chrono::Utc::now().num_days_from_ce();
-				if day != bot_data.last_day {
-					if let Some(ping_squad) = bot_data.bills.get(&82) {
-						for &subscriber in &ping_squad.subscribers {
-							let Some(recipient_id) = bot_data.users.account_owner(subscriber) else {
-								continue;
-							};
-							let embed = Embed::standard().with_title("Cheesebot Online").with_description(format!(
-								"Cheesebot is now online. You received this message because you are subscribed to the {} bill.",
-								&ping_squad.name
-							));
-							if let Err(e) = dm_embed(client, embed, recipient_id).await {
-								error!("Failed to notify of cb online {e:?}");
-							}
-						}
-					}
+				if wealth_tax_due(bot_data) {
+					let maintenance = PresenceUpdate::new()
+						.with_status(Status::Dnd)
+						.with_activities(Activity::custom("Maintenance: applying the balance tax"));
+					update_presence(manager, maintenance).await;
+					check_wealth_tax(bot_data, client).await;
+				}
 
-					bot_data.last_day = day;
-					treasury_balance(bot_data, client).await;
-					twaddle(bot_data, client).await;
-					check_bills(bot_data, client).await;
+				daily_tasks(bot_data, client).await;
+				update_presence(manager, treasury_presence(bot_data)).await;
+				presence_balance = bot_data.treasury_account().balance;
+			}
+			MainMessage::RefreshPresence => {
+				let balance = bot_data.treasury_account().balance;
+				if balance != presence_balance {
+					update_presence(manager, treasury_presence(bot_data)).await;
+					presence_balance = balance;
 				}
 			}
+			#[cfg(feature = "http")]
+			MainMessage::Interaction(interaction) => handle_interaction(interaction, client, bot_data).await,
 			MainMessage::CheckElection => {}
 		}
 	}
 }
 
+/// Posts the daily reports and notifications if they have not been sent today
+async fn daily_tasks(bot_data: &mut BotData, client: &mut DiscordClient) {
+	let day = chrono::Utc::now().num_days_from_ce();
+	if day == bot_data.last_day {
+		return;
+	}
+
+	if let Some(ping_squad) = bot_data.bills.get(&82) {
+		for &subscriber in &ping_squad.subscribers {
+			let Some(recipient_id) = bot_data.users.account_owner(subscriber) else {
+				continue;
+			};
+			let embed = Embed::standard().with_title("Cheesebot Online").with_description(format!(
+				"Cheesebot is now online. You received this message because you are subscribed to the {} bill.",
+				ping_squad.name
+			));
+			if let Err(e) = dm_embed(client, embed, recipient_id).await {
+				log_dm_error(&e, format_args!("Failed to notify of cb online"));
+			}
+		}
+	}
+
+	bot_data.last_day = day;
+	treasury_balance(bot_data, client).await;
+	twaddle(bot_data, client).await;
+	check_bills(bot_data, client).await;
+}
+
+/// Receives interactions over http instead of the gateway (e.g. behind a reverse proxy)
+#[cfg(feature = "http")]
+async fn run_http(address: String, public_key: String) {
+	// Without the gateway there are no events to keep a cache up to date
+	let mut client = DiscordClient::new(include_str!("token.txt").trim());
+	let path = "cheese_data.ron";
+	let mut bot_data = load_bot_data(path);
+	bot_data.file_path = path.to_string();
+
+	let server = InteractionServer::bind(address, &public_key).await.unwrap();
+	let application = Application::get_current_application(&mut client).await.unwrap();
+	create_commands(&mut client, &application.id).await;
+
+	let (send_ev, recieve_ev) = async_channel::unbounded();
+	start_timers(&send_ev);
+	tokio::spawn(async move {
+		while let Some(interaction) = server.next().await {
+			if send_ev.send(MainMessage::Interaction(interaction)).await.is_err() {
+				return;
+			}
+		}
+		send_ev.close();
+	});
+
+	event_loop(&mut client, &mut bot_data, recieve_ev, None).await;
+}
+
+#[test]
+fn pay_end_to_end() {
+	tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.unwrap()
+		.block_on(async {
+			let mock = discord::mock::MockDiscord::start().await;
+			let mut client = mock.client("token");
+
+			let mut bot_data = BotData::default();
+			let alice = User::new().with_id("100").with_username("Alice").with_discriminator("0");
+			let bob = User::new().with_id("200").with_username("Bob").with_discriminator("0");
+			let alice_account = bot_data.cheese_user_mut(&alice).account;
+			let bob_account = bot_data.cheese_user_mut(&bob).account;
+			bot_data.accounts.account_mut(alice_account).unwrap().balance = 1000;
+
+			let interaction = serde_json::json!({
+				"id": "1",
+				"application_id": "mock-application",
+				"type": 2,
+				"token": "interaction-token",
+				"channel_id": "300",
+				"member": {"user": alice, "roles": []},
+				"data": {"name": "pay", "type": 1, "options": [
+					{"name": "recipient", "type": 3, "value": bob_account.to_string()},
+					{"name": "cheesecoin", "type": 10, "value": 1.5},
+					{"name": "from", "type": 3, "value": alice_account.to_string()},
+				]},
+			});
+			let script = mock.clone();
+			tokio::spawn(async move {
+				script
+					.wait_until(|mock| mock.gateway_received().iter().any(|payload| payload["op"] == 2))
+					.await;
+				script.dispatch("INTERACTION_CREATE", interaction).await;
+				script.wait_until(|mock| !mock.interaction_callbacks().is_empty()).await;
+				// An authentication failure stops the shard rather than reconnecting
+				script.close_gateway(4004).await;
+			});
+
+			let path = std::env::temp_dir().join("cheese_data_pay_end_to_end.ron");
+			run(&mut client, &mut bot_data, path.to_str().unwrap()).await.unwrap();
+
+			assert_eq!(bot_data.accounts.account(alice_account).unwrap().balance, 850);
+			assert_eq!(bot_data.accounts.account(bob_account).unwrap().balance, 150);
+			let callback = &mock.interaction_callbacks()[0];
+			let description = callback["data"]["embeds"][0]["description"].as_str().unwrap();
+			assert_eq!(description, "Successfully transfered 1.50cc from Alice to Bob.");
+			let dms = mock.channel_messages();
+			assert_eq!(dms.len(), 1);
+			assert!(dms[0].1["embeds"][0]["description"]
+				.as_str()
+				.unwrap()
+				.contains("has received 1.50cc from Alice"));
+		});
+}
+
 fn main() {
 	init_logger();
 
@@ -556,5 +713,13 @@ fn main() {
 		.enable_all()
 		.build()
 		.unwrap()
-		.block_on(run_loop());
+		.block_on(async {
+			// `bot http <address> <public key>` receives interactions over http rather than connecting to the gateway
+			let args = std::env::args().collect::<Vec<_>>();
+			match &args[1..] {
+				#[cfg(feature = "http")]
+				[mode, address, public_key] if mode == "http" => run_http(address.clone(), public_key.clone()).await,
+				_ => run_loop().await,
+			}
+		});
 }
@@ -1,10 +1,11 @@
 use crate::bot_data::*;
+use crate::create_commands::*;
 use crate::utilities::*;
 use discord::*;
 
 /// Handles the `/orgainsation create` command
-pub async fn organisation_create<'a>(handler_data: &mut HandlerData<'a>) {
-	let org_name = handler_data.options["name"].as_str();
+pub async fn organisation_create<'a>(handler_data: &mut HandlerData<'a>, command: OrganisationCreate) {
+	let org_name = command.name;
 
 	let name = org_name.clone();
 	let account = Account {
@@ -31,10 +32,10 @@ pub async fn organisation_create<'a>(handler_data: &mut HandlerData<'a>) {
 	.await;
 }
 
-pub async fn organisation_transfer<'a>(handler_data: &mut HandlerData<'a>) {
+pub async fn organisation_transfer<'a>(handler_data: &mut HandlerData<'a>, command: OrganisationTransfer) {
 	let bot_data = &mut handler_data.bot_data;
 
-	let organisation = match account_option(bot_data, &handler_data.options["name"], BotData::account_owned, &handler_data.user).await {
+	let organisation = match account_option(bot_data, &command.name, BotData::account_owned, &handler_data.user).await {
 		Some(x) => x,
 		None => {
 			respond_with_embed(
@@ -46,14 +47,7 @@ pub async fn organisation_transfer<'a>(handler_data: &mut HandlerData<'a>) {
 		}
 	};
 
-	let owner_account = match account_option(
-		bot_data,
-		&handler_data.options["owner"],
-		BotData::personal_account_exists,
-		&handler_data.user,
-	)
-	.await
-	{
+	let owner_account = match account_option(bot_data, &command.owner, BotData::personal_account_exists, &handler_data.user).await {
 		Some(x) => x,
 		None => {
 			respond_with_embed(handler_data, Embed::standard().with_title("Transfer").with_description("Invalid owner")).await;
@@ -92,10 +86,10 @@ pub async fn organisation_transfer<'a>(handler_data: &mut HandlerData<'a>) {
 	.await;
 }
 
-pub async fn organisation_rename<'a>(handler_data: &mut HandlerData<'a>) {
+pub async fn organisation_rename<'a>(handler_data: &mut HandlerData<'a>, command: OrganisationRename) {
 	let bot_data = &mut handler_data.bot_data;
 
-	let organisation = match account_option(bot_data, &handler_data.options["name"], BotData::account_owned, &handler_data.user).await {
+	let organisation = match account_option(bot_data, &command.name, BotData::account_owned, &handler_data.user).await {
 		Some(x) => x,
 		None => {
 			respond_with_embed(
@@ -107,7 +101,7 @@ pub async fn organisation_rename<'a>(handler_data: &mut HandlerData<'a>) {
 		}
 	};
 
-	let org_name = handler_data.options["new"].as_str();
+	let org_name = command.new;
 
 	let Some(organisation) = handler_data.bot_data.accounts.organisation_accounts.get_mut(&organisation) else {
 		respond_with_embed(
@@ -128,10 +122,10 @@ pub async fn organisation_rename<'a>(handler_data: &mut HandlerData<'a>) {
 	.await;
 }
 
-pub async fn organisation_delete<'a>(handler_data: &mut HandlerData<'a>) {
+pub async fn organisation_delete<'a>(handler_data: &mut HandlerData<'a>, command: OrganisationDelete) {
 	let bot_data = &mut handler_data.bot_data;
 
-	let organisation = match account_option(bot_data, &handler_data.options["name"], BotData::account_owned, &handler_data.user).await {
+	let organisation = match account_option(bot_data, &command.name, BotData::account_owned, &handler_data.user).await {
 		Some(x) => x,
 		None => {
 			respond_with_embed(
@@ -1,10 +1,11 @@
 use crate::bot_data::*;
+use crate::create_commands::*;
 use crate::general_commands::ELECTION_ADMIN_ROLE;
 use crate::utilities::*;
 use discord::*;
 
 /// Handles the `/parliament add party` and `/parliament delete party` commands
-pub async fn set_running<'a>(handler_data: &mut HandlerData<'a>, title: &str, new_running: bool) {
+pub async fn set_running<'a>(handler_data: &mut HandlerData<'a>, title: &str, new_running: bool, party: String) {
 	let is_valid = is_election_admin(handler_data).await;
 
 	if !is_valid {
@@ -12,10 +13,11 @@ pub async fn set_running<'a>(handler_data: &mut HandlerData<'a>, title: &str, ne
 		return;
 	}
 
-	let party = handler_data.options["party"].as_str();
 	let already_running = handler_data.bot_data.parties.contains_key(&party);
 
 	let descripition = match (already_running, new_running) {
+		// The name is used as the value of the ballot's options, which can be at most 100 characters
+		(false, true) if party.chars().count() > 100 => "Party names can be at most 100 characters.".to_string(),
 		(false, false) => format!("The party named {party} still doesn't exist."),
 		(true, true) => format!("The party named {party} still exists."),
 		(false, true) => {
@@ -31,24 +33,93 @@ pub async fn set_running<'a>(handler_data: &mut HandlerData<'a>, title: &str, ne
 	respond_with_embed(handler_data, Embed::standard().with_title(title).with_description(descripition)).await;
 }
 /// Handles the `/parliament vote` command
-pub async fn vote<'a>(handler_data: &mut HandlerData<'a>) {
-	let party = handler_data.options["party"].as_str();
-
-	let embed = if handler_data.bot_data.parties.contains_key(&party) {
-		let voter = &handler_data.user.id;
-		for (candidate, votes) in &mut handler_data.bot_data.parties {
-			votes.retain(|e| e != voter);
-			if candidate == &party {
-				votes.push(voter.clone());
-			}
+///
+/// Without a party this responds with a ballot (a select menu of the parties) which is handled by [`vote_component`]
+pub async fn vote<'a>(handler_data: &mut HandlerData<'a>, command: Vote) {
+	let Some(party) = command.party else {
+		let mut parties = handler_data.bot_data.parties().collect::<Vec<_>>();
+		parties.sort_unstable();
+		if parties.is_empty() {
+			let embed = Embed::standard().with_title("Vote failed").with_description("No parties are running");
+			respond_with_disappear_embed(handler_data, embed).await;
+			return;
 		}
-		Embed::standard().with_title("Vote cast")
-	} else {
-		Embed::standard().with_title("Vote failed").with_description("Invalid party")
+
+		let mut menu = SelectMenu::new()
+			.with_custom_id(CustomId::new("vote", ""))
+			.with_placeholder("Choose a party");
+		// Select menus can have at most 25 options, with labels of at most 100 characters
+		for party in parties.into_iter().take(25) {
+			let label = party.chars().take(100).collect::<String>();
+			menu = menu.with_options(SelectOption::new().with_label(label).with_value(party));
+		}
+		let ballot = ChannelMessage::new()
+			.with_content("Vote for your party:")
+			.with_components(ActionRows::new().with_components(menu))
+			.with_flags(1_u32 << 6);
+		respond_with_message(handler_data, ballot).await;
+		return;
 	};
+
+	let embed = cast_vote(handler_data, &party);
 	respond_with_message(handler_data, ChannelMessage::new().with_embeds(embed).with_flags(1_u32 << 6)).await;
 }
 
+/// Handles choosing a party on a ballot (`vote:` with the party selected, or `vote:party` for a button)
+pub async fn vote_component<'a>(handler_data: &mut HandlerData<'a>) {
+	let payload = handler_data.custom_id.as_ref().map(|custom_id| custom_id.payload.clone());
+	let party = handler_data.values.first().cloned().or(payload).unwrap_or_default();
+
+	let embed = cast_vote(handler_data, &party);
+	// Replace the ballot with the result
+	let mut message = ChannelMessage::new().with_content("").with_embeds(embed);
+	message.components = Some(Vec::new());
+	update_message(handler_data, message).await;
+}
+
+/// Handles the buttons on ballots sent before custom ids had a prefix (`vote<candidate id>`), voting for the party on the button's label
+pub async fn legacy_vote_button<'a>(handler_data: &mut HandlerData<'a>) {
+	let custom_id = handler_data
+		.custom_id
+		.as_ref()
+		.map(|custom_id| custom_id.prefix.clone())
+		.unwrap_or_default();
+	let components = handler_data.interaction.message.as_ref().and_then(|message| message.components.as_ref());
+	let label = components
+		.into_iter()
+		.flatten()
+		.flat_map(|component| match component {
+			MessageComponent::ActionRows(row) => row.components.iter().collect(),
+			component => vec![component],
+		})
+		.find_map(|component| match component {
+			MessageComponent::Button(button) if button.custom_id.as_ref() == Some(&custom_id) => button.label.clone(),
+			_ => None,
+		});
+
+	let embed = cast_vote(handler_data, &label.unwrap_or_default());
+	let mut message = ChannelMessage::new().with_content("").with_embeds(embed);
+	message.components = Some(Vec::new());
+	update_message(handler_data, message).await;
+}
+
+/// Records the user's vote for the party (removing any previous vote), returning the embed to respond with
+fn cast_vote(handler_data: &mut HandlerData<'_>, party: &String) -> Embed {
+	if !handler_data.bot_data.parties.contains_key(party) {
+		return Embed::standard().with_title("Vote failed").with_description("Invalid party");
+	}
+	let voter = &handler_data.user.id;
+	for (candidate, votes) in &mut handler_data.bot_data.parties {
+		votes.retain(|e| e != voter);
+		if candidate == party {
+			votes.push(voter.clone());
+		}
+	}
+	Embed::standard()
+		.with_title("Vote cast")
+		.with_description(format!("You voted for {party}."))
+}
+
 pub async fn view_results<'a>(handler_data: &mut HandlerData<'a>) {
 	let description = format_election_results(handler_data);
 	respond_with_embed(
@@ -89,7 +160,7 @@ pub async fn count_results(handler_data: &mut HandlerData<'_>) {
 	handler_data.bot_data.previous_time = chrono::Utc::now();
 	handler_data.bot_data.previous_results = String::new();
 
-	write!(
+	let _ = write!(
 		handler_data.bot_data.previous_results,
 		"\n--- Election on {}---\n\n",
 		chrono::Utc::now().format("%d/%m/%Y %H:%M")
@@ -100,17 +171,26 @@ pub async fn count_results(handler_data: &mut HandlerData<'_>) {
 		handler_data.bot_data.previous_results += &votes.len().to_string();
 		handler_data.bot_data.previous_results += "\n";
 	}
-	for (_, votes) in handler_data.bot_data.parties.iter_mut() {
+	// Every ballot cast, exported before the votes are cleared
+	let mut ballots = "party,voter\n".to_string();
+	for (party, voters) in &handler_data.bot_data.parties {
+		for voter in voters {
+			let _ = writeln!(ballots, "\"{}\",{voter}", party.replace('"', "\"\""));
+		}
+	}
+	for votes in handler_data.bot_data.parties.values_mut() {
 		*votes = Vec::new();
 	}
 
 	handler_data.bot_data.save();
 
 	let description = format_election_results(handler_data);
-	respond_with_embed(handler_data, Embed::standard().with_title("Count results").with_description(description)).await;
+	let embed = Embed::standard().with_title("Count results").with_description(description);
+	let files = [FileUpload::new("ballots.csv", "text/csv", ballots)];
+	respond_with_files(handler_data, ChannelMessage::new().with_embeds(embed), &files).await;
 }
 
 async fn is_election_admin(handler_data: &mut HandlerData<'_>) -> bool {
-	let rolls = GuildMember::get_get_guild_member(handler_data.client, DiscordClient::GUILD_ID, &handler_data.user.id).await;
-	rolls.as_ref().map_or(false, |user| user.roles.contains(&ELECTION_ADMIN_ROLE.to_string()))
+	let rolls = handler_data.client.guild_member(DiscordClient::GUILD_ID, &handler_data.user.id).await;
+	rolls.as_ref().is_ok_and(|user| user.roles.contains(&ELECTION_ADMIN_ROLE.to_string()))
 }
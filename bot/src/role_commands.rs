@@ -1,25 +1,17 @@
 use crate::bot_data::*;
+use crate::create_commands::*;
 use crate::utilities::*;
 use crate::CheeseCoinTy;
-use discord::hyper::Method;
 use discord::*;
 
-pub async fn role_assign(handler_data: &mut HandlerData<'_>) {
-	let guild_id = DiscordClient::GUILD_ID;
+pub async fn role_assign(handler_data: &mut HandlerData<'_>, command: RoleAssign) {
 	let reciever = 722468356711776269;
 
 	let price = 15.;
 	let formatted_price = format_cheesecoin((price * 100.) as CheeseCoinTy);
 
-	let access_colour = |name: &str| {
-		handler_data
-			.options
-			.get(name)
-			.map(|value| value.as_float())
-			.filter(|&val| 0. <= val && val <= 225.)
-			.map(|val| val as u32)
-	};
-	let (Some(r), Some(g), Some(b)) = (access_colour("r"), access_colour("g"), access_colour("b")) else {
+	let access_colour = |val: i64| u8::try_from(val).ok().map(u32::from);
+	let (Some(r), Some(g), Some(b)) = (access_colour(command.r), access_colour(command.g), access_colour(command.b)) else {
 		respond_with_embed(
 			handler_data,
 			Embed::standard().with_title("Assign Role").with_description("Invalid colour."),
@@ -30,19 +22,45 @@ pub async fn role_assign(handler_data: &mut HandlerData<'_>) {
 	info!("RGB ({r}, {g}, {b})");
 	let color = r << 16 | g << 8 | b;
 
-	let user = handler_data.bot_data.cheese_user(&handler_data.user).account;
-	let (_, recipient_message) = transact(handler_data, reciever, user, price);
+	// Updating the role, paying and notifying takes several requests, which can take longer than discord allows before a response
+	defer(handler_data).await;
 
-	if recipient_message.is_none() {
+	// The payment is only made once the role has been updated, so check that it can be made first
+	let user = handler_data.bot_data.cheese_user(&handler_data.user).account;
+	let accounts = &handler_data.bot_data.accounts;
+	let can_afford = accounts.exists(reciever) && accounts.account(user).is_some_and(|account| account.balance as f64 >= price * 100.);
+	if !can_afford {
 		respond_with_embed(
 			handler_data,
 			Embed::standard()
 				.with_title("Assign Role")
-				.with_description(format!("Could not afford role which costs {}.", &formatted_price)),
+				.with_description(format!("Could not afford role which costs {}.", formatted_price)),
 		)
 		.await;
 		return;
-	};
+	}
+
+	if let Err(e) = update_role(handler_data, color).await {
+		error!("Failed to update role {e:?}");
+		let embed = Embed::standard()
+			.with_title("Assign Role")
+			.with_description("Discord could not be reached to update your role, you have not been charged.");
+		respond_with_embed(handler_data, embed).await;
+		return;
+	}
+
+	let (sender_message, recipient_message) = transact(handler_data, reciever, user, price);
+	if recipient_message.is_none() {
+		error!("Failed to charge for an assigned role: {sender_message}");
+	}
+
+	respond_with_embed(
+		handler_data,
+		Embed::standard()
+			.with_title("Assign Role")
+			.with_description(format!("Assigned role for {}", formatted_price)),
+	)
+	.await;
 
 	if let (Some(user_account), Some(id)) = (
 		handler_data.bot_data.accounts.account(user),
@@ -52,59 +70,43 @@ pub async fn role_assign(handler_data: &mut HandlerData<'_>) {
 			handler_data.client,
 			Embed::standard().with_title("Assign Role Payment").with_description(format!(
 				"Your account recived {} from {} purchasing a new role.",
-				&formatted_price, user_account.name
+				formatted_price, user_account.name
 			)),
 			id,
 		)
 		.await
 		{
-			warn!("Failed to dm role {e:?}");
+			log_dm_error(&e, format_args!("Failed to dm role"));
 		}
 	}
+}
 
-	respond_with_embed(
-		handler_data,
-		Embed::standard()
-			.with_title("Assign Role")
-			.with_description(format!("Assigned role for {}", &formatted_price)),
-	)
-	.await;
+/// Sets the colour of the user's role, creating the role and adding it to them if necessary
+async fn update_role(handler_data: &mut HandlerData<'_>, color: u32) -> Result<(), NetError> {
+	let guild_id = DiscordClient::GUILD_ID;
+	let roles_list = handler_data.client.guild_roles(guild_id).await?;
 
-	let roles_list: Vec<Role> = match Role::get_guild_roles(handler_data.client, guild_id).await {
-		Ok(role) => role,
-		Err(e) => {
-			info!("Error fetching role list {e:?}");
-			return;
-		}
-	};
+	// Shows who the role and member changes were made for in the audit log
+	let reason = format!("/role assign by {} ({})", handler_data.user.username, handler_data.user.id);
+	let client = &mut handler_data.client.audit_log_reason(reason);
 	let user = handler_data.bot_data.cheese_user_mut(&handler_data.user);
 	let role = user.role_id.as_ref();
 	let role_id = if let Some(role) = role.and_then(|role| roles_list.into_iter().find(|role_o| &role_o.id == role)) {
-		let role_id = role.id.clone();
 		info!("Update role");
-		Role::new()
-			.with_color(color)
-			.patch_update_role(handler_data.client, guild_id, &role_id)
-			.await
-			.unwrap();
-		role_id
+		Role::new().with_color(color).patch_update_role(client, guild_id, &role.id).await?;
+		role.id
 	} else {
 		info!("Create role");
 		let role = Role::new()
 			.with_name(format!("cb-role-{}", handler_data.user.id))
 			.with_color(color)
-			.post_create_role(handler_data.client, guild_id)
-			.await
-			.unwrap();
+			.post_create_role(client, guild_id)
+			.await?;
 		user.role_id = Some(role.id.clone());
 		role.id
 	};
-	let uri = format!(
-		"{}/guilds/{}/members/{}/roles/{}",
-		DiscordClient::API,
-		guild_id,
-		handler_data.user.id,
-		role_id
-	);
-	handler_data.client.request(&uri, "{}".to_string(), Method::PUT).await.unwrap();
+	MemberRole::new()
+		.put_add_member_role(client, guild_id, &handler_data.user.id, role_id)
+		.await?;
+	Ok(())
 }
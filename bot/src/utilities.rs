@@ -1,19 +1,106 @@
-use std::collections::HashMap;
-
 use discord::*;
 
 use crate::{bot_data::*, CheeseCoinTy};
 
 /// Utility function for responding to an interaction with a message
+///
+/// If the response has been deferred, this edits the original response instead.
 pub async fn respond_with_message<'a>(handler_data: &mut HandlerData<'a>, message: ChannelMessage) {
-	InteractionCallback::new(InteractionResponse::ChannelMessageWithSource { data: message })
+	let response = if handler_data.deferred {
+		message
+			.patch_original(
+				handler_data.client,
+				&handler_data.interaction.application_id,
+				&handler_data.interaction.token,
+			)
+			.await
+			.map(|_| ())
+	} else {
+		InteractionCallback::new(InteractionResponse::ChannelMessageWithSource { data: message })
+			.post_respond(
+				handler_data.client,
+				handler_data.interaction.id.clone(),
+				handler_data.interaction.token.clone(),
+			)
+			.await
+			.map(|_| ())
+	};
+	if let Err(e) = response {
+		error!("Failed to respond to interaction {e:?}");
+	}
+}
+
+/// Utility function for responding to an interaction with a message and files attached
+pub async fn respond_with_files<'a>(handler_data: &mut HandlerData<'a>, message: ChannelMessage, files: &[FileUpload]) {
+	let response = if handler_data.deferred {
+		message
+			.patch_original_with_files(
+				handler_data.client,
+				&handler_data.interaction.application_id,
+				&handler_data.interaction.token,
+				files,
+			)
+			.await
+			.map(|_| ())
+	} else {
+		InteractionCallback::new(InteractionResponse::ChannelMessageWithSource { data: message })
+			.post_respond_with_files(
+				handler_data.client,
+				handler_data.interaction.id.clone(),
+				handler_data.interaction.token.clone(),
+				files,
+			)
+			.await
+			.map(|_| ())
+	};
+	if let Err(e) = response {
+		error!("Failed to respond to interaction with files {e:?}");
+	}
+}
+
+/// Utility function for responding to a message component interaction by editing the message the component is on
+pub async fn update_message<'a>(handler_data: &mut HandlerData<'a>, message: ChannelMessage) {
+	let response = InteractionCallback::new(InteractionResponse::UpdateMessage { data: message })
 		.post_respond(
 			handler_data.client,
 			handler_data.interaction.id.clone(),
 			handler_data.interaction.token.clone(),
 		)
-		.await
-		.unwrap();
+		.await;
+	if let Err(e) = response {
+		error!("Failed to update message {e:?}");
+	}
+}
+
+/// Utility function for acknowledging an interaction (showing "thinking") so that slow commands can respond later than the 3 second deadline
+///
+/// Later responses edit the original response, which can be done for 15 minutes.
+pub async fn defer<'a>(handler_data: &mut HandlerData<'a>) {
+	let response = InteractionCallback::new(InteractionResponse::DeferredChannelMessageWithSource)
+		.post_respond(
+			handler_data.client,
+			handler_data.interaction.id.clone(),
+			handler_data.interaction.token.clone(),
+		)
+		.await;
+	match response {
+		Ok(_) => handler_data.deferred = true,
+		Err(e) => error!("Failed to defer interaction {e:?}"),
+	}
+}
+
+/// Utility function for sending an additional message after the interaction has been responded to
+pub async fn respond_followup<'a>(handler_data: &mut HandlerData<'a>, message: ChannelMessage) {
+	let response = message
+		.post_followup(
+			handler_data.client,
+			&handler_data.interaction.application_id,
+			&handler_data.interaction.token,
+		)
+		.await;
+	if let Err(e) = response {
+		error!("Failed to send followup {e:?}");
+	}
 }
 
 /// Utility function for responding to an interaction with an embed
@@ -25,34 +112,72 @@ pub async fn respond_with_disappear_embed<'a>(handler_data: &mut HandlerData<'a>
 	respond_with_message(handler_data, ChannelMessage::new().with_embeds(embed).with_flags(1_u32 << 6)).await;
 }
 
+/// The number of times sending a dm is retried after a temporary failure
+const DM_RETRIES: u32 = 2;
+
 /// Utility function for dming a discord user a message
-pub async fn dm_message<'a>(client: &mut DiscordClient, message: ChannelMessage, recipient_id: String) -> Result<(), NetError> {
+pub async fn dm_message(client: &mut DiscordClient, message: ChannelMessage, recipient_id: String) -> Result<(), NetError> {
 	// We first create the channel (does nothing if it already exists)
 	let channel = CreateDM { recipient_id }.post_create(client).await?;
 
-	// Then we can send the message in the channel
-	message.post_create(client, channel.id).await?;
-
-	Ok(())
+	// Then we can send the message in the channel (trying again with a backoff if discord had a temporary problem)
+	let mut attempt = 0;
+	loop {
+		match message.post_create(client, &channel.id).await {
+			Ok(_) => return Ok(()),
+			Err(e) if e.is_retryable() && attempt < DM_RETRIES => {
+				let delay = tokio::time::Duration::from_millis(500 << attempt);
+				warn!("Retrying dm in {delay:?} after {e:?}");
+				tokio::time::sleep(delay).await;
+				attempt += 1;
+			}
+			Err(e) => return Err(e),
+		}
+	}
 }
 
 /// Utility function for dming a discord user an embed
-pub async fn dm_embed<'a>(client: &mut DiscordClient, embed: Embed, recipient_id: String) -> Result<(), NetError> {
+pub async fn dm_embed(client: &mut DiscordClient, embed: Embed, recipient_id: String) -> Result<(), NetError> {
 	dm_message(client, ChannelMessage::new().with_embeds(embed), recipient_id).await
 }
 
+/// Logs a failed dm as an error, unless the user has closed their dms (which is expected so is only logged as info)
+pub fn log_dm_error(e: &NetError, description: std::fmt::Arguments) {
+	if e.is_dm_closed() {
+		info!("{description}: the user does not accept dms");
+	} else {
+		error!("{description}: {e:?}");
+	}
+}
+
 /// Utility function to extract an account from a slash command option
-pub async fn account_option<'a, V>(bot_data: &mut BotData, option: &OptionType, validation: V, user: &User) -> Option<u64>
+pub async fn account_option<V>(bot_data: &mut BotData, option: &str, validation: V, user: &User) -> Option<u64>
 where
 	V: Fn(&BotData, AccountId, &User) -> bool,
 {
-	let parse_int = str::parse::<AccountId>(&option.as_str());
+	let parse_int = str::parse::<AccountId>(option);
 	match parse_int.map(|id| (id, validation(bot_data, id, user))) {
 		Ok((id, true)) => Some(id),
 		_ => None,
 	}
 }
 
+/// Utility function to read an option, responding with the error if the interaction does not have a valid value
+pub async fn read_option<'a, T>(handler_data: &mut HandlerData<'a>, read: impl FnOnce(&CommandOptions) -> Result<T, OptionError>) -> Option<T> {
+	match read(&handler_data.options) {
+		Ok(value) => Some(value),
+		Err(e) => {
+			warn!("Invalid option {e}");
+			respond_with_disappear_embed(
+				handler_data,
+				Embed::standard().with_title("Invalid Option").with_description(e.to_string()),
+			)
+			.await;
+			None
+		}
+	}
+}
+
 /// Utility function for formating cheesecoin as `4.23cc`
 pub fn format_cheesecoin(cc: CheeseCoinTy) -> String {
 	format!("{:.2}cc", cc as f64 / 100.)
@@ -66,53 +191,68 @@ pub struct ConstructedData<'a> {
 
 /// Construct the data handler (for implementing commands) from the specified interaction
 ///
-/// This creates a new account if necessary, as well as flattenning subcommands into a space seperated string and finding the focused field
-pub fn construct_handler_data<'a>(mut interaction: Interaction, client: &'a mut DiscordClient, bot_data: &'a mut BotData) -> ConstructedData<'a> {
+/// This creates a new account if necessary, as well as flattenning subcommands into a space seperated string and finding the focused field.
+/// Fails with the reason if the interaction is missing the user or the command.
+pub fn construct_handler_data<'a>(
+	mut interaction: Interaction,
+	client: &'a mut DiscordClient,
+	bot_data: &'a mut BotData,
+) -> Result<ConstructedData<'a>, &'static str> {
 	// Extract the user from the interaction (if in guild, then interaction["member"]["user"], if in dms then interaction["user"])
-	let user = (interaction)
-		.user
-		.as_ref()
-		.unwrap_or_else(|| &interaction.member.as_ref().unwrap().user)
-		.clone();
+	let user = match (&interaction.user, &interaction.member) {
+		(Some(user), _) => user.clone(),
+		(None, Some(member)) => member.user.clone(),
+		(None, None) => return Err("the interaction has no user"),
+	};
+	let mut data = interaction.data.take().ok_or("the interaction has no data")?;
 
 	// If the user does not already have an account, create a new one.
 	bot_data.cheese_user_mut(&user);
 
-	let mut data = interaction.data.take().unwrap();
-
+	// Message components and modals are routed by the prefix of their custom id
 	if let Some(custom_id) = data.custom_id.take() {
-		return ConstructedData {
-			command: custom_id,
+		let custom_id = CustomId::parse(&custom_id);
+		info!("Component {}, payload {:?}", custom_id.prefix, custom_id.payload);
+		// The text inputs of a modal are used as the options
+		let options = data
+			.text_inputs()
+			.filter_map(|text_input| Some((text_input.custom_id.clone()?, OptionType::String(text_input.value.clone()?))))
+			.collect();
+		return Ok(ConstructedData {
+			command: custom_id.prefix.clone(),
 			focused: None,
 			handler_data: HandlerData {
 				client,
 				bot_data,
 				interaction,
 				user,
-				options: HashMap::new(),
+				options,
+				deferred: false,
+				custom_id: Some(custom_id),
+				values: data.values.take().unwrap_or_default(),
 			},
-		};
+		});
 	}
 	// Extracts the command name (including sub commands)
-	let mut options = data.options.take().unwrap_or(Vec::new());
-	let mut command = data.name.unwrap();
-	while options.len() > 0
+	let mut options = data.options.take().unwrap_or_default();
+	let mut command = data.name.ok_or("the interaction has no command name or custom id")?;
+	while !options.is_empty()
 		&& (options[0].option_type == CommandOptionType::SubCommandGroup || options[0].option_type == CommandOptionType::SubCommand)
 	{
 		command += " ";
 		command += &options[0].name;
-		options = options[0].options.take().unwrap_or(Vec::new());
+		options = options[0].options.take().unwrap_or_default();
 	}
 
 	// Extracts the focused field
-	let focused = options.iter().find(|o| o.focused.unwrap_or(false)).map(|v| v.clone());
+	let focused = options.iter().find(|o| o.focused.unwrap_or(false)).cloned();
 
 	// Extracts the options used
-	let options = options.into_iter().map(|o| (o.name, o.value.unwrap())).collect::<HashMap<_, _>>();
+	let options = CommandOptions::new(options, data.resolved.take());
 
-	info!("Command name {}, options {:?}", command, options.keys());
+	info!("Command name {}, options {:?}", command, options.values.keys());
 
-	ConstructedData {
+	Ok(ConstructedData {
 		command,
 		focused,
 		handler_data: HandlerData {
@@ -121,8 +261,11 @@ pub fn construct_handler_data<'a>(mut interaction: Interaction, client: &'a mut
 			interaction,
 			user,
 			options,
+			deferred: false,
+			custom_id: None,
+			values: Vec::new(),
 		},
-	}
+	})
 }
 
 /// Handles transactions between accounts - returns (payer message, reciever message)
@@ -138,10 +281,10 @@ pub fn transact<'a>(handler_data: &mut HandlerData<'a>, recipient: u64, from: u6
 	// Amount cast into real units
 	let amount = amount as CheeseCoinTy;
 	if !handler_data.bot_data.accounts.exists(recipient) {
-		return (format!("To account does not exist"), None);
+		return ("To account does not exist".to_string(), None);
 	}
 	let Some(from) = handler_data.bot_data.accounts.account_mut(from) else {
-		return (format!("From account does not exist"), None);
+		return ("From account does not exist".to_string(), None);
 	};
 
 	// Check the account can back the transaction
@@ -185,7 +328,7 @@ pub fn enact_print_money<'a>(handler_data: &mut HandlerData<'a>, recipient: u64,
 	let amount = amount as CheeseCoinTy;
 
 	let Some(recipient) = handler_data.bot_data.accounts.account_mut(recipient) else {
-		return (format!("To account does not exist"), None);
+		return ("To account does not exist".to_string(), None);
 	};
 	recipient.balance = recipient.balance.saturating_add(amount);
 
@@ -196,6 +339,18 @@ pub fn enact_print_money<'a>(handler_data: &mut HandlerData<'a>, recipient: u64,
 	(sender_message, Some(reciever_message))
 }
 
+/// Formats the wealth tax bands with one band on each line, e.g. `Below 10.00cc: 5.00%`
+pub fn format_wealth_tax(bot_data: &BotData) -> String {
+	let bands = bot_data.wealth_tax.iter().map(|&(amount, tax_rate)| {
+		if amount == u32::MAX as CheeseCoinTy || amount == CheeseCoinTy::MAX {
+			format!("No limit: {tax_rate:.2}%")
+		} else {
+			format!("Below {}: {tax_rate:.2}%", format_cheesecoin(amount))
+		}
+	});
+	bands.collect::<Vec<_>>().join("\n")
+}
+
 pub fn format_bill(bill: &Bill, account_name: String) -> String {
 	format!(
 		"{} - {} to {} every {}{}",
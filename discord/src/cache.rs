@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use crate::{Channel, Dispatch, Guild, GuildMember, Role};
+
+/// A guild in the [`Cache`], with everything indexed by id
+#[derive(Debug, Default, Clone)]
+pub struct CachedGuild {
+	pub id: String,
+	pub name: String,
+	pub roles: HashMap<String, Role>,
+	/// Members by user id (only those sent in `GUILD_CREATE`, joined since or fetched)
+	pub members: HashMap<String, GuildMember>,
+	pub channels: HashMap<String, Channel>,
+	/// If `GUILD_CREATE` has been received, before which only the few events seen since are known
+	pub complete: bool,
+}
+
+impl From<Guild> for CachedGuild {
+	fn from(guild: Guild) -> Self {
+		Self {
+			roles: guild.roles.into_iter().map(|role| (role.id.clone(), role)).collect(),
+			members: guild.members.into_iter().map(|member| (member.user.id.clone(), member)).collect(),
+			channels: guild
+				.channels
+				.into_iter()
+				.map(|channel| (channel.id.clone(), channel.with_guild_id(&guild.id)))
+				.collect(),
+			id: guild.id,
+			name: guild.name,
+			complete: true,
+		}
+	}
+}
+
+/// The guilds the bot is in, kept up to date with gateway events
+///
+/// Feed every received dispatch into [`Cache::observe`]. Enable it on a client with [`crate::DiscordClient::with_cache`].
+#[derive(Debug, Default, Clone)]
+pub struct Cache {
+	pub guilds: HashMap<String, CachedGuild>,
+}
+
+impl Cache {
+	/// Updates the cache from a dispatch received on the gateway
+	pub fn observe(&mut self, dispatch: &Dispatch) {
+		match dispatch {
+			Dispatch::GuildCreate(guild) => {
+				self.guilds.insert(guild.id.clone(), guild.clone().into());
+			}
+			Dispatch::GuildMemberAdd(event) | Dispatch::GuildMemberUpdate(event) => {
+				self.insert_member(&event.guild_id, event.member.clone());
+			}
+			Dispatch::GuildMemberRemove(event) => {
+				self.guild_mut(&event.guild_id).members.remove(&event.user.id);
+			}
+			Dispatch::GuildRoleCreate(event) | Dispatch::GuildRoleUpdate(event) => {
+				self.insert_role(&event.guild_id, event.role.clone());
+			}
+			Dispatch::GuildRoleDelete(event) => {
+				let guild = self.guild_mut(&event.guild_id);
+				guild.roles.remove(&event.role_id);
+				for member in guild.members.values_mut() {
+					member.roles.retain(|role| role != &event.role_id);
+				}
+			}
+			Dispatch::ChannelCreate(channel) => self.insert_channel(channel.clone()),
+			Dispatch::ChannelDelete(channel) => {
+				if let Some(guild) = channel.guild_id.as_ref().and_then(|guild_id| self.guilds.get_mut(guild_id)) {
+					guild.channels.remove(&channel.id);
+				}
+			}
+			_ => {}
+		}
+	}
+
+	/// A member of a guild, `None` if they are not cached or the guild has not been received
+	pub fn member(&self, guild_id: &str, user_id: &str) -> Option<&GuildMember> {
+		self.complete_guild(guild_id)?.members.get(user_id)
+	}
+
+	/// The roles of a guild, `None` if the guild has not been received
+	pub fn roles(&self, guild_id: &str) -> Option<Vec<Role>> {
+		Some(self.complete_guild(guild_id)?.roles.values().cloned().collect())
+	}
+
+	/// Gets a guild once `GUILD_CREATE` has been received for it
+	fn complete_guild(&self, guild_id: &str) -> Option<&CachedGuild> {
+		self.guilds.get(guild_id).filter(|guild| guild.complete)
+	}
+
+	/// Finds a channel in any guild
+	pub fn channel(&self, channel_id: &str) -> Option<&Channel> {
+		self.guilds.values().find_map(|guild| guild.channels.get(channel_id))
+	}
+
+	pub fn insert_member(&mut self, guild_id: &str, member: GuildMember) {
+		self.guild_mut(guild_id).members.insert(member.user.id.clone(), member);
+	}
+
+	pub fn insert_role(&mut self, guild_id: &str, role: Role) {
+		self.guild_mut(guild_id).roles.insert(role.id.clone(), role);
+	}
+
+	/// Adds a channel to its guild (channels without a guild, such as dms, are not cached)
+	pub fn insert_channel(&mut self, channel: Channel) {
+		if let Some(guild_id) = channel.guild_id.clone() {
+			self.guild_mut(&guild_id).channels.insert(channel.id.clone(), channel);
+		}
+	}
+
+	/// Gets a guild, adding an empty one if it has not been received yet
+	fn guild_mut(&mut self, guild_id: &str) -> &mut CachedGuild {
+		self.guilds.entry(guild_id.to_string()).or_insert_with(|| CachedGuild {
+			id: guild_id.to_string(),
+			..Default::default()
+		})
+	}
+}
+
+#[test]
+fn cache_dispatches() {
+	let dispatch = |t: &str, d: serde_json::Value| serde_json::from_value::<Dispatch>(serde_json::json!({"t": t, "d": d})).unwrap();
+	let user = serde_json::json!({"id": "2", "username": "Cheese", "discriminator": "0"});
+	let mut cache = Cache::default();
+
+	// Events before the guild is received only give part of the guild, so they are not used to answer
+	cache.observe(&dispatch(
+		"GUILD_ROLE_CREATE",
+		serde_json::json!({"guild_id": "1", "role": {"id": "6", "name": "mp", "color": 0}}),
+	));
+	cache.observe(&dispatch(
+		"GUILD_MEMBER_ADD",
+		serde_json::json!({"guild_id": "1", "user": user, "roles": []}),
+	));
+	assert!(cache.roles("1").is_none() && cache.member("1", "2").is_none());
+
+	cache.observe(&dispatch(
+		"GUILD_CREATE",
+		serde_json::json!({
+			"id": "1",
+			"name": "Cheese Land",
+			"roles": [{"id": "3", "name": "citizen", "color": 0}],
+			"members": [{"user": user, "roles": ["3"]}],
+			"channels": [{"id": "4", "type": 0, "name": "general"}]
+		}),
+	));
+	assert_eq!(cache.member("1", "2").unwrap().roles, vec!["3"]);
+	assert_eq!(cache.channel("4").unwrap().guild_id.as_deref(), Some("1"));
+
+	cache.observe(&dispatch(
+		"GUILD_ROLE_CREATE",
+		serde_json::json!({"guild_id": "1", "role": {"id": "5", "name": "president", "color": 0}}),
+	));
+	cache.observe(&dispatch(
+		"GUILD_MEMBER_UPDATE",
+		serde_json::json!({"guild_id": "1", "user": user, "roles": ["3", "5"]}),
+	));
+	assert_eq!(cache.member("1", "2").unwrap().roles, vec!["3", "5"]);
+
+	cache.observe(&dispatch("GUILD_ROLE_DELETE", serde_json::json!({"guild_id": "1", "role_id": "3"})));
+	assert_eq!(cache.member("1", "2").unwrap().roles, vec!["5"]);
+	assert_eq!(cache.roles("1").unwrap().len(), 1);
+
+	cache.observe(&dispatch("GUILD_MEMBER_REMOVE", serde_json::json!({"guild_id": "1", "user": user})));
+	assert!(cache.member("1", "2").is_none());
+}
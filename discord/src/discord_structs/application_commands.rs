@@ -1,13 +1,14 @@
 use core::panic;
+use std::collections::HashMap;
 
 use crate::GuildMember;
 use crate::User;
 
 use super::prelude::*;
 use super::ChannelMessage;
-use super::MessageComponent;
+use super::{ComponentType, MessageComponent, Permissions, ResolvedData, TextInput};
 
-#[derive(Clone, Debug, Deserialize_repr, Serialize_repr, Default)]
+#[derive(Clone, Debug, Deserialize_repr, Serialize_repr, Default, PartialEq, Eq)]
 #[repr(u8)]
 pub enum CommandType {
 	#[default]
@@ -15,6 +16,22 @@ pub enum CommandType {
 	User = 2,
 	Message = 3,
 }
+/// https://discord.com/developers/docs/interactions/application-commands#interaction-contexts
+#[derive(Clone, Copy, Debug, Deserialize_repr, Serialize_repr, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum InteractionContextType {
+	#[default]
+	Guild = 0,
+	/// In the bot's dms
+	BotDm = 1,
+	/// In group dms and dms other than the bot's
+	PrivateChannel = 2,
+}
+impl InteractionContextType {
+	/// Commands can be used everywhere by default
+	pub const ALL: [Self; 3] = [Self::Guild, Self::BotDm, Self::PrivateChannel];
+}
+
 #[derive(Clone, Debug, Deserialize_repr, Serialize_repr, Default)]
 #[repr(u8)]
 pub enum InteractionType {
@@ -41,27 +58,43 @@ pub enum CommandOptionType {
 	Number = 10,
 	Attachment = 11,
 }
-#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+/// The value of an option (users, roles, channels and attachments are sent as ids, see [`CommandOptions`] to look them up)
+#[derive(Clone, Debug, Deserialize, Serialize, Default, PartialEq)]
 #[serde(untagged)]
 pub enum OptionType {
 	#[default]
 	None,
+	Bool(bool),
 	String(String),
-	Integer(u64),
+	Integer(i64),
 	Number(f64),
 }
 
 impl OptionType {
-	pub fn as_str(&self) -> String {
+	pub fn as_str(&self) -> Option<&str> {
 		match self {
-			OptionType::String(x) => x.to_string(),
-			_ => unimplemented!("Invalid type for str"),
+			OptionType::String(x) => Some(x),
+			_ => None,
 		}
 	}
-	pub fn as_float(&self) -> f64 {
+	pub fn as_int(&self) -> Option<i64> {
 		match self {
-			OptionType::Number(x) => *x,
-			_ => unimplemented!("Invalid type for float"),
+			OptionType::Integer(x) => Some(*x),
+			_ => None,
+		}
+	}
+	/// The value of a number option (which are sent as integers when they are whole)
+	pub fn as_float(&self) -> Option<f64> {
+		match self {
+			OptionType::Number(x) => Some(*x),
+			OptionType::Integer(x) => Some(*x as f64),
+			_ => None,
+		}
+	}
+	pub fn as_bool(&self) -> Option<bool> {
+		match self {
+			OptionType::Bool(x) => Some(*x),
+			_ => None,
 		}
 	}
 }
@@ -74,24 +107,79 @@ pub struct Modal {
 }
 
 #[discord_struct]
+#[derive(PartialEq)]
 pub struct ApplicationCommandOptionChoice {
 	name: String,
 	value: OptionType,
 }
 #[discord_struct]
+#[derive(PartialEq)]
 pub struct ApplicationCommandOption {
 	#[serde(rename = "type")]
 	option_type: CommandOptionType,
 	name: String,
 	description: String,
+	// Discord leaves these out of registered commands when they are false
+	#[serde(default)]
 	required: bool,
+	#[serde(default)]
 	autocomplete: bool,
 	choices: Option<Vec<ApplicationCommandOptionChoice>>,
 	options: Option<Vec<ApplicationCommandOption>>,
+	/// The smallest number allowed for integer and number options
+	min_value: Option<f64>,
+	/// The largest number allowed for integer and number options
+	max_value: Option<f64>,
+	/// The shortest text allowed for string options
+	min_length: Option<u16>,
+	/// The longest text allowed for string options
+	max_length: Option<u16>,
+	/// The types of channel that can be chosen for channel options
+	channel_types: Option<Vec<u8>>,
+	/// The name in other languages, by locale (e.g. `fr`)
+	name_localizations: Option<HashMap<String, String>>,
+	/// The description in other languages, by locale (e.g. `fr`)
+	description_localizations: Option<HashMap<String, String>>,
+}
+impl ApplicationCommandOption {
+	/// Sets the smallest and largest numbers allowed, for integer or number options (builder pattern)
+	pub fn with_range<T: Into<f64>>(self, range: std::ops::RangeInclusive<T>) -> Self {
+		let (min, max) = range.into_inner();
+		self.with_min_value(min.into()).with_max_value(max.into())
+	}
+
+	/// Removes empty lists so that options can be compared with the ones discord sends back
+	///
+	/// Whole numbers are sent back without a decimal point (so are read as integers), so number choices are all made floats.
+	fn normalised(mut self) -> Self {
+		let number = self.option_type == CommandOptionType::Number;
+		self.choices = self.choices.filter(|choices| !choices.is_empty()).map(|choices| {
+			choices
+				.into_iter()
+				.map(|mut choice| {
+					if let Some(value) = choice.value.as_float().filter(|_| number) {
+						choice.value = OptionType::Number(value);
+					}
+					choice
+				})
+				.collect()
+		});
+		self.channel_types = self.channel_types.filter(|channel_types| !channel_types.is_empty());
+		self.name_localizations = self.name_localizations.filter(|localizations| !localizations.is_empty());
+		self.description_localizations = self.description_localizations.filter(|localizations| !localizations.is_empty());
+		self.options = self
+			.options
+			.map(|options| options.into_iter().map(Self::normalised).collect::<Vec<_>>())
+			.filter(|options| !options.is_empty());
+		self
+	}
 }
 
 #[request(add_command = POST "/applications/{application_id}/commands" as application_id)]
+#[request(global_commands return Vec<ApplicationCommand> = GET "/applications/{application_id}/commands" as application_id; with_localizations: bool)]
+#[request(guild_commands return Vec<ApplicationCommand> = GET "/applications/{application_id}/guilds/{guild_id}/commands" as application_id, guild_id; with_localizations: bool)]
 #[discord_struct]
+#[derive(PartialEq)]
 pub struct ApplicationCommand {
 	#[serde(rename = "type")]
 	command_type: Option<CommandType>,
@@ -99,13 +187,181 @@ pub struct ApplicationCommand {
 
 	description: Option<String>,
 	options: Option<Vec<ApplicationCommandOption>>,
+
+	/// The permissions a member needs to see the command by default (empty for only administrators)
+	default_member_permissions: Option<Permissions>,
+	/// Replaced by `contexts`
+	dm_permission: Option<bool>,
+	/// Where the command can be used (only for global commands)
+	contexts: Option<Vec<InteractionContextType>>,
+	/// If the command is age restricted
+	nsfw: Option<bool>,
+	/// The name in other languages, by locale (e.g. `fr`)
+	name_localizations: Option<HashMap<String, String>>,
+	/// The description in other languages, by locale (e.g. `fr`)
+	description_localizations: Option<HashMap<String, String>>,
+}
+impl ApplicationCommand {
+	/// Fills in the defaults and removes empty lists so that commands can be compared with the ones discord sends back
+	fn normalised(mut self) -> Self {
+		self.command_type = self.command_type.or(Some(CommandType::Chat));
+		self.dm_permission = self.dm_permission.filter(|&dm_permission| !dm_permission);
+		self.nsfw = self.nsfw.filter(|&nsfw| nsfw);
+		self.contexts = self.contexts.or_else(|| Some(InteractionContextType::ALL.to_vec()));
+		self.name_localizations = self.name_localizations.filter(|localizations| !localizations.is_empty());
+		self.description_localizations = self.description_localizations.filter(|localizations| !localizations.is_empty());
+		self.options = self
+			.options
+			.map(|options| options.into_iter().map(ApplicationCommandOption::normalised).collect::<Vec<_>>())
+			.filter(|options| !options.is_empty());
+		self
+	}
 }
 
+/// The commands to register, replacing all of the existing ones
 #[request(bulk_override_global as {&self.commands} = PUT "/applications/{application_id}/commands" as application_id)]
+#[request(bulk_override_guild as {&self.commands} = PUT "/applications/{application_id}/guilds/{guild_id}/commands" as application_id, guild_id)]
 #[discord_struct]
 pub struct ApplicationCommandList {
 	commands: Vec<ApplicationCommand>,
 }
+impl ApplicationCommandList {
+	/// Registers the commands globally or in a guild, only replacing the registered commands if they are different
+	pub async fn sync(&self, client: &mut DiscordClient, application_id: &str, guild_id: Option<&str>) -> Result<CommandDiff, NetError> {
+		// Discord leaves out the localizations unless they are asked for, which would make every localized command look changed
+		let registered = match guild_id {
+			Some(guild_id) => ApplicationCommand::get_guild_commands(client, application_id, guild_id, Some(true)).await?,
+			None => ApplicationCommand::get_global_commands(client, application_id, Some(true)).await?,
+		};
+		let diff = CommandDiff::new(&registered, &self.commands);
+		if diff.is_empty() {
+			return Ok(diff);
+		}
+		match guild_id {
+			Some(guild_id) => self.put_bulk_override_guild(client, application_id, guild_id).await?,
+			None => self.put_bulk_override_global(client, application_id).await?,
+		};
+		Ok(diff)
+	}
+}
+
+/// The names of the commands that differ between those registered with discord and new definitions
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CommandDiff {
+	pub added: Vec<String>,
+	pub removed: Vec<String>,
+	pub changed: Vec<String>,
+}
+impl CommandDiff {
+	pub fn new(registered: &[ApplicationCommand], commands: &[ApplicationCommand]) -> Self {
+		fn find<'a>(commands: &'a [ApplicationCommand], name: &str) -> Option<&'a ApplicationCommand> {
+			commands.iter().find(|command| command.name == name)
+		}
+		let mut diff = Self::default();
+		for command in commands {
+			match find(registered, &command.name) {
+				None => diff.added.push(command.name.clone()),
+				Some(old) if old.clone().normalised() != command.clone().normalised() => diff.changed.push(command.name.clone()),
+				Some(_) => {}
+			}
+		}
+		for command in registered {
+			if find(commands, &command.name).is_none() {
+				diff.removed.push(command.name.clone());
+			}
+		}
+		diff
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+	}
+}
+impl std::fmt::Display for CommandDiff {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		if self.is_empty() {
+			return write!(f, "no changes");
+		}
+		write!(f, "added {:?}, removed {:?}, changed {:?}", self.added, self.removed, self.changed)
+	}
+}
+
+#[test]
+fn command_diff() {
+	let option = ApplicationCommandOption::new()
+		.with_option_type(CommandOptionType::String)
+		.with_name("recipient")
+		.with_description("recipient of the payment")
+		.with_required(true);
+	let pay = ApplicationCommand::new()
+		.with_command_type(CommandType::Chat)
+		.with_name("pay")
+		.with_description("Give someone cheesecoins.")
+		.with_options(option);
+	let about = ApplicationCommand::new().with_name("about").with_description("Description of the bot.");
+
+	// Discord sends back ids and leaves out false fields
+	let registered = serde_json::from_str::<Vec<ApplicationCommand>>(
+		r#"[
+			{"id":"1","type":1,"name":"pay","description":"Give someone cheesecoins.","default_member_permissions":null,"dm_permission":true,"nsfw":false,"contexts":null,"options":[{"type":3,"name":"recipient","description":"recipient of the payment","required":true}]},
+			{"id":"2","type":1,"name":"balances","description":"All of your balances.","options":[]}
+		]"#,
+	)
+	.unwrap();
+	assert!(CommandDiff::new(&registered[..1], std::slice::from_ref(&pay)).is_empty());
+
+	// Whole number choices are sent back as integers
+	let amount = ApplicationCommandOption::new()
+		.with_option_type(CommandOptionType::Number)
+		.with_name("amount")
+		.with_description("amount to pay")
+		.with_choices(ApplicationCommandOptionChoice::new().with_name("five").with_value(OptionType::Number(5.)));
+	let registered_amount = serde_json::from_str::<ApplicationCommand>(
+		r#"{"id":"3","type":1,"name":"pay","description":"Give someone cheesecoins.","options":[{"type":3,"name":"recipient","description":"recipient of the payment","required":true},{"type":10,"name":"amount","description":"amount to pay","choices":[{"name":"five","value":5}]}]}"#,
+	)
+	.unwrap();
+	let pay_amount = pay.clone().with_options(amount);
+	assert!(CommandDiff::new(std::slice::from_ref(&registered_amount), std::slice::from_ref(&pay_amount)).is_empty());
+
+	let changed = pay.with_description("Pay someone.");
+	let diff = CommandDiff::new(&registered, &[changed, about]);
+	assert_eq!(
+		diff,
+		CommandDiff {
+			added: vec!["about".to_string()],
+			removed: vec!["balances".to_string()],
+			changed: vec!["pay".to_string()],
+		}
+	);
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn sync_localized_commands() {
+	use crate::mock::MockDiscord;
+	tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.unwrap()
+		.block_on(async {
+			let mock = MockDiscord::start().await;
+			let registered = serde_json::json!([{"id": "1", "type": 1, "name": "about", "description": "Description of the bot.",
+				"name_localizations": {"fr": "apropos"}}]);
+			mock.respond(hyper::Method::GET, "/applications/2/guilds/3/commands", hyper::StatusCode::OK, registered);
+
+			let about = ApplicationCommand::new()
+				.with_name("about")
+				.with_description("Description of the bot.")
+				.with_name_localizations(HashMap::from([("fr".to_string(), "apropos".to_string())]));
+			let commands = ApplicationCommandList::new().with_commands(about);
+			let mut client = mock.client("token");
+			assert!(commands.sync(&mut client, "2", Some("3")).await.unwrap().is_empty());
+
+			let requests = mock.requests();
+			assert_eq!(requests.len(), 1);
+			assert_eq!(requests[0].query.as_deref(), Some("with_localizations=true"));
+		});
+}
 
 #[discord_struct]
 pub struct InteractionDataOption {
@@ -127,6 +383,30 @@ pub struct InteractionData {
 	options: Option<Vec<InteractionDataOption>>,
 
 	custom_id: Option<String>,
+	component_type: Option<ComponentType>,
+	/// The values chosen in a select menu
+	values: Option<Vec<String>>,
+	resolved: Option<ResolvedData>,
+}
+impl InteractionData {
+	/// The text inputs of a submitted modal (which are inside action rows)
+	pub fn text_inputs(&self) -> impl Iterator<Item = &TextInput> {
+		fn flatten(component: &MessageComponent) -> Vec<&TextInput> {
+			match component {
+				MessageComponent::ActionRows(rows) => rows.components.iter().flat_map(flatten).collect(),
+				MessageComponent::TextInput(text_input) => vec![text_input],
+				_ => Vec::new(),
+			}
+		}
+		self.components.iter().flatten().flat_map(flatten)
+	}
+
+	/// The value entered into the text input with the specified custom id in a submitted modal
+	pub fn modal_value(&self, custom_id: &str) -> Option<&str> {
+		self.text_inputs()
+			.find(|text_input| text_input.custom_id.as_deref() == Some(custom_id))
+			.and_then(|text_input| text_input.value.as_deref())
+	}
 }
 
 /// https://discord.com/developers/docs/interactions/receiving-and-responding#interaction-object-interaction-structure
@@ -137,10 +417,13 @@ pub struct Interaction {
 	#[serde(rename = "type")]
 	interaction_type: InteractionType,
 	data: Option<InteractionData>,
-	channel_id: String,
+	/// The channel it was sent from (not sent with pings)
+	channel_id: Option<String>,
 	token: String,
 	member: Option<GuildMember>,
 	user: Option<User>,
+	/// The message a component is on (for message component interactions)
+	message: Option<super::Message>,
 }
 
 #[test]
@@ -151,6 +434,20 @@ fn test_de() {
 	x.unwrap();
 	let y: Interaction = serde_json::from_str(r##"
 {"version":1,"type":3,"token":"aW50ZXJhY3Rpb246MTA3ODA2MzY2OTg3Njg4NzY1Mjo2RDRLVUg2c1FvSTlSa2w0Q2VkR3NMelhiN2pXMUpDMzNWT2VMeTlDZzEyZWUyWWRNc2JWd1psdWJsOE5GZzBCTGZnYUM0OXEzZ1E1eHJxZFBEb3ZDY1o5RTlBa2RHNzJyUGZ0ejVaeHd5R1dpQ0lXYVNpWFVuUVBoaEo5eDY2NA","message":{"webhook_id":"910254320740610069","type":20,"tts":false,"timestamp":"2023-02-22T21:11:38.400000+00:00","pinned":false,"mentions":[],"mention_roles":[],"mention_everyone":false,"interaction":{"user":{"username":"Käse","public_flags":128,"id":"630073509137350690","display_name":null,"discriminator":"3615","avatar_decoration":null,"avatar":"761226738b7c90394e301b7f387fdc9d"},"type":2,"name":"parliament vote","id":"1078061529682956439"},"id":"1078061530978992240","flags":0,"embeds":[],"edited_timestamp":null,"content":"Vote for your candidate:","components":[{"type":1,"components":[{"type":2,"style":2,"label":"Alan","custom_id":"vote887020108696920116"},{"type":2,"style":2,"label":"аe","custom_id":"vote762325231925854231"},{"type":2,"style":2,"label":"Elliot F.","custom_id":"vote722468356711776269"}]}],"channel_id":"956233767767408741","author":{"username":"Cheese Bot","public_flags":0,"id":"910254320740610069","display_name":null,"discriminator":"4538","bot":true,"avatar_decoration":null,"avatar":null},"attachments":[],"application_id":"910254320740610069"},"member":{"user":{"username":"18XYang","public_flags":0,"id":"764206694841974795","display_name":null,"discriminator":"6684","avatar_decoration":null,"avatar":null},"roles":["1078035383511699496","985630968650010705","985804444237172797"],"premium_since":null,"permissions":"539018919105","pending":false,"nick":"Xiao-Kun","mute":false,"joined_at":"2023-02-22T19:00:46.675000+00:00","is_pending":false,"flags":0,"deaf":false,"communication_disabled_until":null,"avatar":null},"locale":"en-GB","id":"1078063669876887652","guild_locale":"en-US","guild_id":"907657508292792342","entitlement_sku_ids":[],"data":{"custom_id":"vote887020108696920116","component_type":2},"channel_id":"956233767767408741","application_id":"910254320740610069","app_permissions":"4398046511103"}"##).unwrap();
+	let components = y.message.unwrap().components.unwrap();
+	assert!(matches!(&components[0], MessageComponent::ActionRows(row) if row.components.len() == 3));
+}
+
+#[test]
+fn modal_submit() {
+	let interaction = serde_json::from_str::<Interaction>(
+		r#"{"id":"1","application_id":"2","type":5,"channel_id":"3","token":"token","user":{"id":"4","username":"Käse","discriminator":"0"},"data":{"custom_id":"decree:","components":[{"type":1,"components":[{"type":4,"custom_id":"title","value":"Cheese"}]},{"type":1,"components":[{"type":4,"custom_id":"description","value":"Line one\nLine two"}]}]}}"#,
+	)
+	.unwrap();
+	let data = interaction.data.unwrap();
+	assert_eq!(data.modal_value("title"), Some("Cheese"));
+	assert_eq!(data.modal_value("description"), Some("Line one\nLine two"));
+	assert_eq!(data.modal_value("missing"), None);
 }
 
 #[discord_struct]
@@ -168,14 +465,14 @@ pub enum InteractionResponse {
 	#[tag(5)]
 	DeferredChannelMessageWithSource,
 	#[tag(7)]
-	UpdateMessage,
+	UpdateMessage { data: ChannelMessage },
 	#[tag(8)]
 	ApplicationCommandAutocompleteResult { data: AutocompleteResult },
 	#[tag(9)]
 	Modal { data: Modal },
 }
 
-#[request(respond as {&self.value} = POST "/interactions/{interaction_id}/{interaction_token}/callback" as interaction_id, interaction_token)]
+#[request(respond as {&self.value} upload = POST "/interactions/{interaction_id}/{interaction_token}/callback" as interaction_id, interaction_token)]
 pub struct InteractionCallback {
 	value: InteractionResponse,
 }
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::{Attachment, Channel, GuildMember, InteractionDataOption, OptionType, Role, User};
+
+/// A member referred to by an option (without the user, which is in [`ResolvedData::users`])
+#[discord_struct]
+pub struct ResolvedMember {
+	nick: Option<String>,
+	#[serde(default)]
+	roles: Vec<String>,
+}
+
+/// The users, members, roles, channels and attachments referred to by the options of an interaction, by id
+///
+/// https://discord.com/developers/docs/interactions/receiving-and-responding#interaction-object-resolved-data-structure
+#[discord_struct]
+pub struct ResolvedData {
+	#[serde(default)]
+	users: HashMap<String, User>,
+	#[serde(default)]
+	members: HashMap<String, ResolvedMember>,
+	#[serde(default)]
+	roles: HashMap<String, Role>,
+	#[serde(default)]
+	channels: HashMap<String, Channel>,
+	#[serde(default)]
+	attachments: HashMap<String, Attachment>,
+}
+
+/// The value of a mentionable option
+#[derive(Clone, Debug)]
+pub enum Mentionable {
+	User(User),
+	Role(Role),
+}
+
+/// Why an option could not be read, caused by a command definition that does not match the handler or a malformed interaction
+#[derive(Clone, Debug, PartialEq)]
+pub enum OptionError {
+	Missing(String),
+	WrongType {
+		name: String,
+		expected: &'static str,
+		value: OptionType,
+	},
+	/// The option has an id that is not in the resolved data
+	Unresolved {
+		name: String,
+		id: String,
+	},
+	/// The command (including the subcommands) has not been defined
+	UnknownCommand(String),
+}
+
+impl fmt::Display for OptionError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			OptionError::Missing(name) => write!(f, "The {name} option is missing."),
+			OptionError::WrongType { name, expected, value } => write!(f, "The {name} option should be a {expected} but is {value:?}."),
+			OptionError::Unresolved { name, id } => write!(f, "The {name} option refers to {id} which could not be found."),
+			OptionError::UnknownCommand(command) => write!(f, "The {command} command does not exist."),
+		}
+	}
+}
+
+impl std::error::Error for OptionError {}
+
+/// The options of a command (after the subcommands have been removed), with typed accessors
+#[derive(Clone, Debug, Default)]
+pub struct CommandOptions {
+	pub values: HashMap<String, OptionType>,
+	pub resolved: ResolvedData,
+}
+
+impl CommandOptions {
+	pub fn new(options: Vec<InteractionDataOption>, resolved: Option<ResolvedData>) -> Self {
+		Self {
+			values: options.into_iter().filter_map(|option| Some((option.name, option.value?))).collect(),
+			resolved: resolved.unwrap_or_default(),
+		}
+	}
+
+	/// The value of an option, `None` if it was not specified
+	pub fn get(&self, name: &str) -> Option<&OptionType> {
+		self.values.get(name)
+	}
+
+	/// Reads an option with the accessor, which returns `None` if the option has the wrong type
+	fn typed<'a, T>(&'a self, name: &str, expected: &'static str, access: impl FnOnce(&'a OptionType) -> Option<T>) -> Result<T, OptionError> {
+		let value = self.get(name).ok_or_else(|| OptionError::Missing(name.to_string()))?;
+		access(value).ok_or_else(|| OptionError::WrongType {
+			name: name.to_string(),
+			expected,
+			value: value.clone(),
+		})
+	}
+
+	/// Looks up the id in an option in one of the maps of resolved data
+	fn resolve<T: Clone>(&self, name: &str, expected: &'static str, map: &HashMap<String, T>) -> Result<T, OptionError> {
+		let id = self.typed(name, expected, OptionType::as_str)?;
+		map.get(id).cloned().ok_or_else(|| OptionError::Unresolved {
+			name: name.to_string(),
+			id: id.to_string(),
+		})
+	}
+
+	pub fn string(&self, name: &str) -> Result<String, OptionError> {
+		self.typed(name, "string", OptionType::as_str).map(str::to_string)
+	}
+
+	pub fn integer(&self, name: &str) -> Result<i64, OptionError> {
+		self.typed(name, "integer", OptionType::as_int)
+	}
+
+	pub fn number(&self, name: &str) -> Result<f64, OptionError> {
+		self.typed(name, "number", OptionType::as_float)
+	}
+
+	pub fn bool(&self, name: &str) -> Result<bool, OptionError> {
+		self.typed(name, "boolean", OptionType::as_bool)
+	}
+
+	pub fn user(&self, name: &str) -> Result<User, OptionError> {
+		self.resolve(name, "user", &self.resolved.users)
+	}
+
+	/// The member for a user option (only in guilds)
+	pub fn member(&self, name: &str) -> Result<GuildMember, OptionError> {
+		let member = self.resolve(name, "user", &self.resolved.members)?;
+		Ok(GuildMember {
+			user: self.user(name)?,
+			nick: member.nick,
+			roles: member.roles,
+		})
+	}
+
+	pub fn role(&self, name: &str) -> Result<Role, OptionError> {
+		self.resolve(name, "role", &self.resolved.roles)
+	}
+
+	pub fn channel(&self, name: &str) -> Result<Channel, OptionError> {
+		self.resolve(name, "channel", &self.resolved.channels)
+	}
+
+	pub fn attachment(&self, name: &str) -> Result<Attachment, OptionError> {
+		self.resolve(name, "attachment", &self.resolved.attachments)
+	}
+
+	pub fn mentionable(&self, name: &str) -> Result<Mentionable, OptionError> {
+		self.user(name).map(Mentionable::User).or_else(|_| self.role(name).map(Mentionable::Role))
+	}
+}
+
+impl FromIterator<(String, OptionType)> for CommandOptions {
+	fn from_iter<I: IntoIterator<Item = (String, OptionType)>>(iter: I) -> Self {
+		Self {
+			values: iter.into_iter().collect(),
+			..Default::default()
+		}
+	}
+}
+
+#[test]
+fn command_options() {
+	let data = serde_json::from_value::<super::InteractionData>(serde_json::json!({
+		"name": "give",
+		"options": [
+			{"name": "amount", "type": 10, "value": 5},
+			{"name": "count", "type": 4, "value": -2},
+			{"name": "note", "type": 3, "value": "cheese"},
+			{"name": "confirm", "type": 5, "value": true},
+			{"name": "to", "type": 6, "value": "1"},
+			{"name": "role", "type": 8, "value": "2"},
+			{"name": "receipt", "type": 11, "value": "3"},
+			{"name": "ghost", "type": 6, "value": "4"}
+		],
+		"resolved": {
+			"users": {"1": {"id": "1", "username": "Cheese", "discriminator": "0"}},
+			"members": {"1": {"nick": "Brie", "roles": ["2"]}},
+			"roles": {"2": {"id": "2", "name": "citizen", "color": 0}},
+			"attachments": {"3": {"id": "3", "filename": "receipt.png"}}
+		}
+	}))
+	.unwrap();
+	let options = CommandOptions::new(data.options.unwrap(), data.resolved);
+
+	assert_eq!(options.number("amount"), Ok(5.));
+	assert_eq!(options.integer("count"), Ok(-2));
+	assert_eq!(options.string("note").as_deref(), Ok("cheese"));
+	assert_eq!(options.bool("confirm"), Ok(true));
+	assert_eq!(options.member("to").unwrap().nick.as_deref(), Some("Brie"));
+	assert_eq!(options.role("role").unwrap().name, "citizen");
+	assert_eq!(options.attachment("receipt").unwrap().filename, "receipt.png");
+	assert!(matches!(options.mentionable("role"), Ok(Mentionable::Role(_))));
+
+	assert_eq!(options.string("missing"), Err(OptionError::Missing("missing".to_string())));
+	assert!(matches!(options.string("amount"), Err(OptionError::WrongType { expected: "string", .. })));
+	assert!(matches!(options.user("ghost"), Err(OptionError::Unresolved { .. })));
+}
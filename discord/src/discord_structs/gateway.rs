@@ -1,3 +1,4 @@
+use super::{Channel, Guild, GuildMember, Intents, Message, Role, User};
 use crate::Interaction;
 
 use super::prelude::*;
@@ -28,11 +29,87 @@ pub struct ConnectionProperties {
 	device: String,
 }
 
+/// https://discord.com/developers/docs/topics/gateway-events#identify
 #[discord_struct]
 pub struct Identify {
 	token: String,
 	properties: ConnectionProperties,
-	intents: u64,
+	intents: Intents,
+	/// The shard id and number of shards (see [`crate::ShardManager`])
+	shard: Option<[u64; 2]>,
+	/// The presence to start with
+	presence: Option<PresenceUpdate>,
+}
+
+/// https://discord.com/developers/docs/topics/gateway-events#update-presence-status-types
+#[derive(Clone, Debug, Deserialize, Serialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+	#[default]
+	Online,
+	/// Do not disturb
+	Dnd,
+	Idle,
+	/// Shown as offline
+	Invisible,
+	Offline,
+}
+
+/// https://discord.com/developers/docs/topics/gateway-events#activity-object-activity-types
+#[derive(Clone, Debug, Deserialize_repr, Serialize_repr, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ActivityType {
+	/// Playing {name}
+	#[default]
+	Game = 0,
+	/// Streaming {details}, requires a url
+	Streaming = 1,
+	/// Listening to {name}
+	Listening = 2,
+	/// Watching {name}
+	Watching = 3,
+	/// {emoji} {state}
+	Custom = 4,
+	/// Competing in {name}
+	Competing = 5,
+}
+
+/// https://discord.com/developers/docs/topics/gateway-events#activity-object
+#[discord_struct]
+pub struct Activity {
+	name: String,
+	#[serde(rename = "type")]
+	activity_type: ActivityType,
+	/// The text of a custom status
+	state: Option<String>,
+	url: Option<String>,
+}
+impl Activity {
+	/// A custom status showing just the text
+	pub fn custom(text: impl Into<String>) -> Self {
+		Self::new()
+			.with_name("Custom Status")
+			.with_activity_type(ActivityType::Custom)
+			.with_state(text)
+	}
+}
+
+/// https://discord.com/developers/docs/topics/gateway-events#update-presence
+#[discord_struct]
+pub struct PresenceUpdate {
+	/// When the bot went idle (unix time in milliseconds), or 0 if it is not idle
+	since: u64,
+	activities: Vec<Activity>,
+	status: Status,
+	afk: bool,
+}
+
+/// https://discord.com/developers/docs/topics/gateway-events#resume
+#[discord_struct]
+pub struct Resume {
+	token: String,
+	session_id: String,
+	seq: usize,
 }
 
 #[discord_struct]
@@ -40,6 +117,7 @@ pub struct Hello {
 	heartbeat_interval: u64,
 }
 
+#[request(current_application = GET "/applications/@me")]
 #[discord_struct]
 pub struct Application {
 	id: String,
@@ -49,19 +127,109 @@ pub struct Application {
 pub struct Ready {
 	v: u64,
 	session_id: String,
+	resume_gateway_url: String,
 	application: Application,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+/// A member joining or being updated (`GUILD_MEMBER_ADD` and `GUILD_MEMBER_UPDATE`)
+#[discord_struct]
+pub struct GuildMemberEvent {
+	guild_id: String,
+	#[serde(flatten)]
+	member: GuildMember,
+}
+
+#[discord_struct]
+pub struct GuildMemberRemove {
+	guild_id: String,
+	user: User,
+}
+
+/// A role being created or updated (`GUILD_ROLE_CREATE` and `GUILD_ROLE_UPDATE`)
+#[discord_struct]
+pub struct GuildRoleEvent {
+	guild_id: String,
+	role: Role,
+}
+
+#[discord_struct]
+pub struct GuildRoleDelete {
+	guild_id: String,
+	role_id: String,
+}
+
+#[discord_struct]
+pub struct MessageCreate {
+	#[serde(flatten)]
+	message: Message,
+	guild_id: Option<String>,
+}
+
+/// A dispatch for an event that has no variant in [`Dispatch`]
+#[discord_struct]
+pub struct UnknownDispatch {
+	t: String,
+	d: serde_json::Value,
+}
+
+/// https://discord.com/developers/docs/topics/gateway-events#receive-events
+#[derive(Clone, Debug, Serialize)]
+#[allow(clippy::large_enum_variant)]
 #[serde(tag = "t", content = "d")]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum Dispatch {
 	InteractionCreate(Interaction),
 	Ready(Ready),
-	GuildCreate,
+	Resumed(serde_json::Value),
+	GuildCreate(Guild),
+	GuildMemberAdd(GuildMemberEvent),
+	GuildMemberUpdate(GuildMemberEvent),
+	GuildMemberRemove(GuildMemberRemove),
+	GuildRoleCreate(GuildRoleEvent),
+	GuildRoleUpdate(GuildRoleEvent),
+	GuildRoleDelete(GuildRoleDelete),
+	MessageCreate(MessageCreate),
+	ChannelCreate(Channel),
+	ChannelDelete(Channel),
+	/// Any other event (so that new events don't fail to deserialize)
+	#[serde(untagged)]
+	Unknown(UnknownDispatch),
+}
+
+impl<'de> Deserialize<'de> for Dispatch {
+	/// Deserialises the data by the event name, so that only events without a variant are [`Dispatch::Unknown`]
+	///
+	/// A known event that doesn't match its struct is logged and kept as unknown rather than closing the connection.
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let UnknownDispatch { t, d } = UnknownDispatch::deserialize(deserializer)?;
+		fn parse<T: serde::de::DeserializeOwned>(d: &serde_json::Value, variant: fn(T) -> Dispatch) -> serde_json::Result<Dispatch> {
+			T::deserialize(d).map(variant)
+		}
+		let dispatch = match t.as_str() {
+			"INTERACTION_CREATE" => parse(&d, Dispatch::InteractionCreate),
+			"READY" => parse(&d, Dispatch::Ready),
+			"RESUMED" => parse(&d, Dispatch::Resumed),
+			"GUILD_CREATE" => parse(&d, Dispatch::GuildCreate),
+			"GUILD_MEMBER_ADD" => parse(&d, Dispatch::GuildMemberAdd),
+			"GUILD_MEMBER_UPDATE" => parse(&d, Dispatch::GuildMemberUpdate),
+			"GUILD_MEMBER_REMOVE" => parse(&d, Dispatch::GuildMemberRemove),
+			"GUILD_ROLE_CREATE" => parse(&d, Dispatch::GuildRoleCreate),
+			"GUILD_ROLE_UPDATE" => parse(&d, Dispatch::GuildRoleUpdate),
+			"GUILD_ROLE_DELETE" => parse(&d, Dispatch::GuildRoleDelete),
+			"MESSAGE_CREATE" => parse(&d, Dispatch::MessageCreate),
+			"CHANNEL_CREATE" => parse(&d, Dispatch::ChannelCreate),
+			"CHANNEL_DELETE" => parse(&d, Dispatch::ChannelDelete),
+			_ => return Ok(Dispatch::Unknown(UnknownDispatch { t, d })),
+		};
+		Ok(dispatch.unwrap_or_else(|e| {
+			warn!("Failed to deserialise {t} dispatch: {e}");
+			Dispatch::Unknown(UnknownDispatch { t, d })
+		}))
+	}
 }
 
 #[derive(Clone, Debug)]
+#[allow(clippy::large_enum_variant)]
 #[serialise_tag("op")]
 pub enum GatewayRecieve {
 	#[tag(0)]
@@ -89,4 +257,60 @@ pub enum GatewaySend {
 	Heartbeat { d: Option<usize> },
 	#[tag(2)]
 	Identify { d: Identify },
+	#[tag(3)]
+	PresenceUpdate { d: PresenceUpdate },
+	#[tag(6)]
+	Resume { d: Resume },
+}
+
+#[test]
+fn unknown_dispatch() {
+	let member_add = r#"{"t":"GUILD_MEMBER_ADD","s":2,"op":0,"d":{"guild_id":"1","user":{"id":"2","username":"Käse","discriminator":"0"},"roles":[],"joined_at":"2023-02-22T19:00:46.675000+00:00"}}"#;
+	let GatewayRecieve::Dispatch {
+		d: Dispatch::GuildMemberAdd(member_add),
+		..
+	} = serde_json::from_str(member_add).unwrap()
+	else {
+		panic!("Expected member add");
+	};
+	assert_eq!(member_add.member.user.username, "Käse");
+
+	let unknown = r#"{"t":"STAGE_INSTANCE_CREATE","s":3,"op":0,"d":{"id":"1"}}"#;
+	let GatewayRecieve::Dispatch {
+		d: Dispatch::Unknown(unknown),
+		..
+	} = serde_json::from_str(unknown).unwrap()
+	else {
+		panic!("Expected unknown dispatch");
+	};
+	assert_eq!(unknown.t, "STAGE_INSTANCE_CREATE");
+
+	// A known event with the wrong data is kept (and logged) rather than failing the whole message
+	let malformed = r#"{"t":"GUILD_ROLE_DELETE","s":4,"op":0,"d":{"guild_id":"1"}}"#;
+	let GatewayRecieve::Dispatch {
+		d: Dispatch::Unknown(malformed),
+		..
+	} = serde_json::from_str(malformed).unwrap()
+	else {
+		panic!("Expected the malformed dispatch to be unknown");
+	};
+	assert_eq!(malformed.t, "GUILD_ROLE_DELETE");
+
+	let serialised = serde_json::to_value(Dispatch::GuildRoleDelete(GuildRoleDelete::new().with_guild_id("1").with_role_id("2"))).unwrap();
+	assert!(matches!(serde_json::from_value(serialised), Ok(Dispatch::GuildRoleDelete(_))));
+}
+
+#[test]
+fn presence_update() {
+	let presence = PresenceUpdate::new()
+		.with_status(Status::Dnd)
+		.with_activities(Activity::custom("Treasury: 1234.00cc"));
+	let json = serde_json::to_value(GatewaySend::PresenceUpdate { d: presence }).unwrap();
+	assert_eq!(
+		json,
+		serde_json::json!({
+			"op": 3,
+			"d": {"since": 0, "activities": [{"name": "Custom Status", "type": 4, "state": "Treasury: 1234.00cc"}], "status": "dnd", "afk": false}
+		})
+	);
 }
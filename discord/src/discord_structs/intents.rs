@@ -1,36 +1,60 @@
-pub enum Intents {
-	Guilds = 1 << 0,
-	GuildMembers = 1 << 1,
-	GuildBans = 1 << 2,
-	GuildEmojis = 1 << 3,
-	GuildIntegrations = 1 << 4,
-	GuildWebhooks = 1 << 5,
-	GuildInvites = 1 << 6,
-	GuildVoiceStates = 1 << 7,
-	GuildPresences = 1 << 8,
-	GuildMessages = 1 << 9,
-	GuildMessageReactions = 1 << 10,
-	GuildMessageTyping = 1 << 11,
-	DirectMessages = 1 << 12,
-	DirectMessageReactions = 1 << 13,
-	DirectMessageTyping = 1 << 14,
+use super::prelude::*;
+
+bitflags::bitflags! {
+	/// The events the gateway sends (some are privileged and have to be enabled in the developer portal)
+	///
+	/// https://discord.com/developers/docs/topics/gateway#gateway-intents
+	#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+	pub struct Intents: u64 {
+		const GUILDS = 1 << 0;
+		const GUILD_MEMBERS = 1 << 1;
+		const GUILD_MODERATION = 1 << 2;
+		const GUILD_EMOJIS_AND_STICKERS = 1 << 3;
+		const GUILD_INTEGRATIONS = 1 << 4;
+		const GUILD_WEBHOOKS = 1 << 5;
+		const GUILD_INVITES = 1 << 6;
+		const GUILD_VOICE_STATES = 1 << 7;
+		const GUILD_PRESENCES = 1 << 8;
+		const GUILD_MESSAGES = 1 << 9;
+		const GUILD_MESSAGE_REACTIONS = 1 << 10;
+		const GUILD_MESSAGE_TYPING = 1 << 11;
+		const DIRECT_MESSAGES = 1 << 12;
+		const DIRECT_MESSAGE_REACTIONS = 1 << 13;
+		const DIRECT_MESSAGE_TYPING = 1 << 14;
+		const MESSAGE_CONTENT = 1 << 15;
+		const GUILD_SCHEDULED_EVENTS = 1 << 16;
+		const AUTO_MODERATION_CONFIGURATION = 1 << 20;
+		const AUTO_MODERATION_EXECUTION = 1 << 21;
+	}
 }
 
-#[allow(dead_code)]
-pub const INTENTS_ALL_WITHOUT_PRIVILEDGED: u64 = Intents::Guilds as u64
-	| Intents::GuildBans as u64
-	| Intents::GuildEmojis as u64
-	| Intents::GuildIntegrations as u64
-	| Intents::GuildWebhooks as u64
-	| Intents::GuildInvites as u64
-	| Intents::GuildVoiceStates as u64
-	| Intents::GuildMessages as u64
-	| Intents::GuildMessageReactions as u64
-	| Intents::GuildMessageTyping as u64
-	| Intents::DirectMessages as u64
-	| Intents::DirectMessageReactions as u64
-	| Intents::DirectMessageTyping as u64;
-#[allow(dead_code)]
-pub const INTENTS_ALL: u64 = INTENTS_ALL_WITHOUT_PRIVILEDGED | Intents::GuildMembers as u64 | Intents::GuildPresences as u64;
-#[allow(dead_code)]
-pub const INTENTS_NONE: u64 = 0;
+impl Intents {
+	/// The intents which have to be enabled in the developer portal
+	pub const PRIVILEGED: Self = Self::GUILD_MEMBERS.union(Self::GUILD_PRESENCES).union(Self::MESSAGE_CONTENT);
+
+	/// All of the intents that do not need to be enabled in the developer portal
+	pub const fn non_privileged() -> Self {
+		Self::all().difference(Self::PRIVILEGED)
+	}
+}
+
+// Intents are sent as an integer
+impl Serialize for Intents {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_u64(self.bits())
+	}
+}
+
+impl<'de> Deserialize<'de> for Intents {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		u64::deserialize(deserializer).map(Self::from_bits_retain)
+	}
+}
+
+#[test]
+fn intents() {
+	let intents = Intents::GUILDS | Intents::GUILD_MEMBERS;
+	assert_eq!(serde_json::to_string(&intents).unwrap(), "3");
+	assert_eq!(serde_json::from_str::<Intents>("3").unwrap(), intents);
+	assert!(!Intents::non_privileged().intersects(Intents::PRIVILEGED));
+}
@@ -1,6 +1,6 @@
 use super::prelude::*;
 
-#[derive(Clone, Debug, Deserialize_repr, Serialize_repr, Default)]
+#[derive(Clone, Debug, Deserialize_repr, Serialize_repr, Default, PartialEq, Eq)]
 #[repr(u8)]
 pub enum ComponentType {
 	#[default]
@@ -66,6 +66,7 @@ pub struct TextInput {
 	placeholder: Option<String>,
 	min_length: Option<i32>,
 	max_length: Option<i32>,
+	required: Option<bool>,
 }
 
 #[derive(Clone, Debug)]
@@ -100,3 +101,41 @@ impl From<TextInput> for MessageComponent {
 		Self::TextInput(text_input)
 	}
 }
+
+/// A component's `custom_id`, made of a prefix (used to route the interaction to a handler) and a payload, formatted as `prefix:payload`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CustomId {
+	pub prefix: String,
+	pub payload: String,
+}
+impl CustomId {
+	pub fn new(prefix: impl Into<String>, payload: impl std::fmt::Display) -> Self {
+		Self {
+			prefix: prefix.into(),
+			payload: payload.to_string(),
+		}
+	}
+
+	/// Splits a `custom_id` at the first `:` (a `custom_id` without a `:` is treated as just a prefix)
+	pub fn parse(custom_id: &str) -> Self {
+		let (prefix, payload) = custom_id.split_once(':').unwrap_or((custom_id, ""));
+		Self::new(prefix, payload)
+	}
+}
+impl std::fmt::Display for CustomId {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}:{}", self.prefix, self.payload)
+	}
+}
+impl From<CustomId> for String {
+	fn from(custom_id: CustomId) -> Self {
+		custom_id.to_string()
+	}
+}
+
+#[test]
+fn custom_id_round_trip() {
+	let custom_id = CustomId::new("vote", "Cheese Party: Reformed");
+	assert_eq!(CustomId::parse(&custom_id.to_string()), custom_id);
+	assert_eq!(CustomId::parse("vote887020108696920116"), CustomId::new("vote887020108696920116", ""));
+}
@@ -1,23 +1,125 @@
-use super::MessageComponent;
+use super::{MessageComponent, User};
 
 use super::prelude::*;
 
+/// https://discord.com/developers/docs/resources/message#embed-object
 #[discord_struct]
 pub struct Embed {
 	title: String,
 	description: String,
+	url: Option<String>,
 	timestamp: String,
 	color: i32,
+	footer: Option<EmbedFooter>,
+	image: Option<EmbedMedia>,
+	thumbnail: Option<EmbedMedia>,
+	provider: Option<EmbedProvider>,
+	author: Option<EmbedAuthor>,
+	fields: Option<Vec<EmbedField>>,
 }
 impl Embed {
+	/// The maximum number of fields discord allows on an embed
+	pub const MAX_FIELDS: usize = 25;
+
 	pub fn standard() -> Self {
 		Self::new().with_color(0xFAA61A).with_timestamp(chrono::Utc::now().to_rfc3339())
 	}
+
+	/// Adds a field, truncating the name and value to discord's limits (builder pattern)
+	///
+	/// Discord rejects fields with an empty name or value so they are replaced with a zero width space, and fields past
+	/// [`Embed::MAX_FIELDS`] are dropped with a warning.
+	pub fn with_field(self, name: impl Into<String>, value: impl Into<String>, inline: bool) -> Self {
+		let (name, value) = (name.into(), value.into());
+		if self.fields.as_ref().is_some_and(|fields| fields.len() >= Self::MAX_FIELDS) {
+			warn!("Embed {:?} has too many fields, dropping field {:?}", self.title, name);
+			return self;
+		}
+		let truncate = |text: String, max: usize| match text.char_indices().nth(max) {
+			Some((index, _)) => text[..index].to_string(),
+			None if text.is_empty() => "\u{200b}".to_string(),
+			None => text,
+		};
+		let field = EmbedField::new()
+			.with_name(truncate(name, 256))
+			.with_value(truncate(value, 1024))
+			.with_inline(inline);
+		self.with_fields(field)
+	}
+}
+
+#[test]
+fn embed_fields() {
+	let embed = Embed::standard()
+		.with_field("", "", true)
+		.with_field("é".repeat(300), "v".repeat(2000), false);
+	let fields = embed.fields.as_ref().unwrap();
+	assert_eq!((fields[0].name.as_str(), fields[0].value.as_str()), ("\u{200b}", "\u{200b}"));
+	assert_eq!((fields[1].name.chars().count(), fields[1].value.len()), (256, 1024));
+
+	let embed = (0..30).fold(Embed::standard(), |embed, index| embed.with_field(index.to_string(), "value", true));
+	let fields = embed.fields.unwrap();
+	assert_eq!((fields.len(), fields[24].name.as_str()), (Embed::MAX_FIELDS, "24"));
+}
+
+#[discord_struct]
+pub struct EmbedField {
+	name: String,
+	value: String,
+	inline: Option<bool>,
+}
+
+#[discord_struct]
+pub struct EmbedFooter {
+	text: String,
+	icon_url: Option<String>,
+}
+
+/// An image, thumbnail or video on an embed
+#[discord_struct]
+pub struct EmbedMedia {
+	url: String,
+	height: Option<u32>,
+	width: Option<u32>,
+}
+
+#[discord_struct]
+pub struct EmbedProvider {
+	name: Option<String>,
+	url: Option<String>,
 }
 
+#[discord_struct]
+pub struct EmbedAuthor {
+	name: String,
+	url: Option<String>,
+	icon_url: Option<String>,
+}
+
+/// https://discord.com/developers/docs/resources/channel#channel-object
+#[request(channel = GET "/channels/{channel_id}" as channel_id)]
 #[discord_struct]
 pub struct Channel {
 	id: String,
+	#[serde(rename = "type", default)]
+	channel_type: u8,
+	guild_id: Option<String>,
+	name: Option<String>,
+	parent_id: Option<String>,
+}
+
+/// Changes to a guild channel, only the fields that are set are changed
+///
+/// https://discord.com/developers/docs/resources/channel#modify-channel
+#[request(modify_channel return Channel = PATCH "/channels/{channel_id}" as channel_id)]
+#[discord_struct]
+pub struct ModifyChannel {
+	name: Option<String>,
+	topic: Option<String>,
+	/// The category the channel is in
+	parent_id: Option<String>,
+	/// The number of seconds a user has to wait between sending messages (0-21600)
+	rate_limit_per_user: Option<u32>,
 }
 
 #[request(create return Channel = POST "/users/@me/channels")]
@@ -26,11 +128,51 @@ pub struct CreateDM {
 	recipient_id: String,
 }
 
-#[request(create = POST "/channels/{channel_id}/messages" as channel_id)]
+/// A file attached to a message
+///
+/// When uploading, the id is the index of the file in the upload and only the attachments listed are kept when editing.
+#[discord_struct]
+pub struct Attachment {
+	id: String,
+	filename: String,
+	description: Option<String>,
+	content_type: Option<String>,
+	size: Option<u64>,
+	url: Option<String>,
+}
+
+/// A message to send (or edit to)
+#[request(create return Message upload = POST "/channels/{channel_id}/messages" as channel_id)]
+#[request(message return Message upload = PATCH "/channels/{channel_id}/messages/{message_id}" as channel_id, message_id)]
+#[request(original return Message upload = PATCH "/webhooks/{application_id}/{interaction_token}/messages/@original" as application_id, interaction_token)]
+#[request(original = DELETE "/webhooks/{application_id}/{interaction_token}/messages/@original" as application_id, interaction_token)]
+#[request(followup return Message upload = POST "/webhooks/{application_id}/{interaction_token}" as application_id, interaction_token)]
 #[discord_struct]
 pub struct ChannelMessage {
 	content: Option<String>,
 	embeds: Option<Vec<Embed>>,
 	components: Option<Vec<MessageComponent>>,
+	attachments: Option<Vec<Attachment>>,
 	flags: Option<u32>,
 }
+
+/// A message that has been sent
+///
+/// https://discord.com/developers/docs/resources/message#message-object
+#[request(message = GET "/channels/{channel_id}/messages/{message_id}" as channel_id, message_id)]
+#[request(message = DELETE "/channels/{channel_id}/messages/{message_id}" as channel_id, message_id)]
+#[request(history return Vec<Message> = GET "/channels/{channel_id}/messages" as channel_id; around: String, before: String, after: String, limit: u8)]
+#[discord_struct]
+pub struct Message {
+	id: String,
+	channel_id: String,
+	author: User,
+	content: String,
+	#[serde(default)]
+	embeds: Vec<Embed>,
+	#[serde(default)]
+	attachments: Vec<Attachment>,
+	components: Option<Vec<MessageComponent>>,
+	timestamp: String,
+	edited_timestamp: Option<String>,
+}
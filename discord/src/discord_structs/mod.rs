@@ -1,16 +1,20 @@
 mod application_commands;
+mod command_options;
 mod gateway;
 mod intents;
 mod message_components;
 mod messages;
+mod permissions;
 mod threads;
 mod user;
 
 pub use application_commands::*;
+pub use command_options::*;
 pub use gateway::*;
 pub use intents::*;
 pub use message_components::*;
 pub use messages::*;
+pub use permissions::*;
 pub use threads::*;
 pub use user::*;
 
@@ -24,5 +28,5 @@ mod prelude {
 	pub use serde_repr::Deserialize_repr;
 	pub use serde_repr::Serialize_repr;
 
-	pub use crate::{DiscordClient, NetError};
+	pub use crate::{DiscordClient, FileUpload, NetError};
 }
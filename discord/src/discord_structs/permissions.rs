@@ -0,0 +1,72 @@
+use super::prelude::*;
+
+bitflags::bitflags! {
+	/// What a member is allowed to do in a guild or channel
+	///
+	/// https://discord.com/developers/docs/topics/permissions#permissions-bitwise-permission-flags
+	#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+	pub struct Permissions: u64 {
+		const CREATE_INSTANT_INVITE = 1 << 0;
+		const KICK_MEMBERS = 1 << 1;
+		const BAN_MEMBERS = 1 << 2;
+		const ADMINISTRATOR = 1 << 3;
+		const MANAGE_CHANNELS = 1 << 4;
+		const MANAGE_GUILD = 1 << 5;
+		const ADD_REACTIONS = 1 << 6;
+		const VIEW_AUDIT_LOG = 1 << 7;
+		const PRIORITY_SPEAKER = 1 << 8;
+		const STREAM = 1 << 9;
+		const VIEW_CHANNEL = 1 << 10;
+		const SEND_MESSAGES = 1 << 11;
+		const SEND_TTS_MESSAGES = 1 << 12;
+		const MANAGE_MESSAGES = 1 << 13;
+		const EMBED_LINKS = 1 << 14;
+		const ATTACH_FILES = 1 << 15;
+		const READ_MESSAGE_HISTORY = 1 << 16;
+		const MENTION_EVERYONE = 1 << 17;
+		const USE_EXTERNAL_EMOJIS = 1 << 18;
+		const VIEW_GUILD_INSIGHTS = 1 << 19;
+		const CONNECT = 1 << 20;
+		const SPEAK = 1 << 21;
+		const MUTE_MEMBERS = 1 << 22;
+		const DEAFEN_MEMBERS = 1 << 23;
+		const MOVE_MEMBERS = 1 << 24;
+		const USE_VAD = 1 << 25;
+		const CHANGE_NICKNAME = 1 << 26;
+		const MANAGE_NICKNAMES = 1 << 27;
+		const MANAGE_ROLES = 1 << 28;
+		const MANAGE_WEBHOOKS = 1 << 29;
+		const MANAGE_GUILD_EXPRESSIONS = 1 << 30;
+		const USE_APPLICATION_COMMANDS = 1 << 31;
+		const REQUEST_TO_SPEAK = 1 << 32;
+		const MANAGE_EVENTS = 1 << 33;
+		const MANAGE_THREADS = 1 << 34;
+		const CREATE_PUBLIC_THREADS = 1 << 35;
+		const CREATE_PRIVATE_THREADS = 1 << 36;
+		const USE_EXTERNAL_STICKERS = 1 << 37;
+		const SEND_MESSAGES_IN_THREADS = 1 << 38;
+		const USE_EMBEDDED_ACTIVITIES = 1 << 39;
+		const MODERATE_MEMBERS = 1 << 40;
+	}
+}
+
+// Permissions are sent as a string because they can be larger than javascript numbers
+impl Serialize for Permissions {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(&self.bits().to_string())
+	}
+}
+
+impl<'de> Deserialize<'de> for Permissions {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let bits = String::deserialize(deserializer)?;
+		bits.parse().map(Self::from_bits_retain).map_err(D::Error::custom)
+	}
+}
+
+#[test]
+fn permissions() {
+	let permissions = Permissions::MANAGE_ROLES | Permissions::MODERATE_MEMBERS;
+	assert_eq!(serde_json::to_string(&permissions).unwrap(), r#""1099780063232""#);
+	assert_eq!(serde_json::from_str::<Permissions>(r#""1099780063232""#).unwrap(), permissions);
+}
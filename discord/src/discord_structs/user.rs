@@ -1,3 +1,5 @@
+use super::Channel;
+
 use super::prelude::*;
 
 #[discord_struct]
@@ -9,6 +11,7 @@ pub struct User {
 }
 
 #[request(get_guild_member = GET "/guilds/{guild_id}/members/{user_id}" as guild_id, user_id)]
+#[request(guild_members return Vec<GuildMember> = GET "/guilds/{guild_id}/members" as guild_id; limit: u16, after: String)]
 #[discord_struct]
 pub struct GuildMember {
 	user: User,
@@ -16,6 +19,31 @@ pub struct GuildMember {
 	roles: Vec<String>,
 }
 
+/// Changes to a guild member, only the fields that are set are changed
+///
+/// https://discord.com/developers/docs/resources/guild#modify-guild-member
+#[request(modify_guild_member return GuildMember = PATCH "/guilds/{guild_id}/members/{user_id}" as guild_id, user_id)]
+#[discord_struct]
+pub struct ModifyGuildMember {
+	nick: Option<String>,
+	/// Replaces all of the member's roles
+	roles: Option<Vec<String>>,
+	/// When the member's timeout ends (ISO8601 timestamp)
+	communication_disabled_until: Option<String>,
+}
+impl ModifyGuildMember {
+	/// Times out the member for the specified duration (builder pattern)
+	pub fn with_timeout(self, duration: chrono::Duration) -> Self {
+		self.with_communication_disabled_until((chrono::Utc::now() + duration).to_rfc3339())
+	}
+}
+
+/// Adds or removes a single role from a guild member
+#[request(add_member_role = PUT "/guilds/{guild_id}/members/{user_id}/roles/{role_id}" as guild_id, user_id, role_id)]
+#[request(remove_member_role = DELETE "/guilds/{guild_id}/members/{user_id}/roles/{role_id}" as guild_id, user_id, role_id)]
+#[discord_struct]
+pub struct MemberRole {}
+
 #[request(create_role return Role = POST "/guilds/{guild_id}/roles" as guild_id)]
 #[request(update_role = PATCH "/guilds/{guild_id}/roles/{role_id}" as guild_id, role_id)]
 #[request(guild_roles return Vec<Role> = GET "/guilds/{guild_id}/roles" as guild_id)]
@@ -26,3 +54,19 @@ pub struct Role {
 	name: String,
 	color: u32,
 }
+
+/// A guild with the roles, members and channels sent in `GUILD_CREATE`
+///
+/// https://discord.com/developers/docs/resources/guild#guild-object
+#[discord_struct]
+pub struct Guild {
+	id: String,
+	#[serde(default)]
+	name: String,
+	#[serde(default)]
+	roles: Vec<Role>,
+	#[serde(default)]
+	members: Vec<GuildMember>,
+	#[serde(default)]
+	channels: Vec<Channel>,
+}
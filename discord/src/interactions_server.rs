@@ -0,0 +1,192 @@
+//! Receives interactions as http requests from discord instead of over the gateway
+//!
+//! Enabled with the `http-interactions` feature. Set the interactions endpoint url in the developer portal to wherever this is served
+//! (usually behind a reverse proxy that handles tls).
+//!
+//! https://discord.com/developers/docs/interactions/overview#setting-up-an-endpoint
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use async_channel::{Receiver, Sender};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+use crate::{Interaction, InteractionResponse, InteractionType};
+
+/// A http server which verifies and receives interactions
+///
+/// Pings are answered automatically, every other interaction is acknowledged with `202 Accepted` and should be responded to
+/// with an [`crate::InteractionCallback`] as if it came from the gateway.
+pub struct InteractionServer {
+	/// The address the server is listening on
+	pub address: SocketAddr,
+	interactions: Receiver<Interaction>,
+}
+
+impl InteractionServer {
+	/// Listens on the address, checking requests with the application's public key (hex encoded, from the developer portal)
+	pub async fn bind(address: impl ToSocketAddrs, public_key: &str) -> std::io::Result<Self> {
+		let invalid_key = |e| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Invalid public key {e}"));
+		let public_key = hex::decode(public_key.trim()).map_err(|e| invalid_key(e.to_string()))?;
+		let public_key = public_key.try_into().map_err(|_| invalid_key("length".to_string()))?;
+		let public_key = VerifyingKey::from_bytes(&public_key).map_err(|e| invalid_key(e.to_string()))?;
+
+		let listener = TcpListener::bind(address).await?;
+		let address = listener.local_addr()?;
+		info!("Listening for interactions on {address}");
+
+		let (send, interactions) = async_channel::unbounded();
+		tokio::spawn(async move {
+			while let Ok((stream, _)) = listener.accept().await {
+				tokio::spawn(connection(public_key, send.clone(), stream));
+			}
+		});
+
+		Ok(Self { address, interactions })
+	}
+
+	/// Waits for the next interaction
+	pub async fn next(&self) -> Option<Interaction> {
+		self.interactions.recv().await.ok()
+	}
+}
+
+/// Checks that discord signed the request (the signature is of the timestamp followed by the body)
+///
+/// https://discord.com/developers/docs/interactions/overview#setting-up-an-endpoint-validating-security-request-headers
+pub fn verify(public_key: &VerifyingKey, signature: &str, timestamp: &str, body: &[u8]) -> bool {
+	let Some(signature) = hex::decode(signature).ok().and_then(|signature| Signature::from_slice(&signature).ok()) else {
+		return false;
+	};
+	let message = [timestamp.as_bytes(), body].concat();
+	public_key.verify(&message, &signature).is_ok()
+}
+
+/// How old a request's timestamp can be (in seconds) before it is rejected, so that captured requests can not be replayed
+pub const MAX_REQUEST_AGE: i64 = 5 * 60;
+
+/// Checks that the request's timestamp (in seconds since the unix epoch) is within [`MAX_REQUEST_AGE`] of now
+pub fn is_recent(timestamp: &str) -> bool {
+	timestamp
+		.parse::<i64>()
+		.is_ok_and(|timestamp| (chrono::Utc::now().timestamp() - timestamp).abs() <= MAX_REQUEST_AGE)
+}
+
+/// Handles a http connection from discord
+async fn connection(public_key: VerifyingKey, send: Sender<Interaction>, stream: TcpStream) {
+	let service = service_fn(move |request| interaction_request(public_key, send.clone(), request));
+	if let Err(e) = hyper::server::conn::http1::Builder::new()
+		.serve_connection(TokioIo::new(stream), service)
+		.await
+	{
+		debug!("Interaction connection failed {e:?}");
+	}
+}
+
+/// Verifies a request and forwards the interaction
+async fn interaction_request(
+	public_key: VerifyingKey,
+	send: Sender<Interaction>,
+	request: Request<Incoming>,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+	let respond = |status: StatusCode, body: String| Ok(Response::builder().status(status).body(Full::new(Bytes::from(body))).unwrap());
+	if request.method() != Method::POST {
+		return respond(StatusCode::METHOD_NOT_ALLOWED, String::new());
+	}
+
+	let header = |name: &str| {
+		request
+			.headers()
+			.get(name)
+			.and_then(|value| value.to_str().ok())
+			.unwrap_or_default()
+			.to_string()
+	};
+	let (signature, timestamp) = (header("X-Signature-Ed25519"), header("X-Signature-Timestamp"));
+	let Ok(body) = request.into_body().collect().await.map(|body| body.to_bytes()) else {
+		return respond(StatusCode::BAD_REQUEST, String::new());
+	};
+	// Discord sends requests with invalid signatures to check that they are rejected
+	if !verify(&public_key, &signature, &timestamp, &body) {
+		warn!("Rejected interaction with an invalid signature");
+		return respond(StatusCode::UNAUTHORIZED, "invalid request signature".to_string());
+	}
+	if !is_recent(&timestamp) {
+		warn!("Rejected interaction with the old timestamp {timestamp}");
+		return respond(StatusCode::UNAUTHORIZED, "request timestamp too old".to_string());
+	}
+
+	let interaction = match serde_json::from_slice::<Interaction>(&body) {
+		Ok(interaction) => interaction,
+		Err(e) => {
+			error!("Error decoding interaction {e:?} {}", String::from_utf8_lossy(&body));
+			return respond(StatusCode::BAD_REQUEST, String::new());
+		}
+	};
+	if matches!(interaction.interaction_type, InteractionType::Ping) {
+		return respond(StatusCode::OK, serde_json::to_string(&InteractionResponse::Pong).unwrap());
+	}
+
+	if send.send(interaction).await.is_err() {
+		return respond(StatusCode::SERVICE_UNAVAILABLE, String::new());
+	}
+	respond(StatusCode::ACCEPTED, String::new())
+}
+
+#[test]
+fn signed_requests() {
+	use ed25519_dalek::{Signer, SigningKey};
+	use hyper_util::client::legacy::Client;
+	use hyper_util::rt::TokioExecutor;
+
+	let signing_key = SigningKey::from_bytes(&[7; 32]);
+	let public_key = hex::encode(signing_key.verifying_key().as_bytes());
+	let signed = |body: &str, timestamp: &str| hex::encode(signing_key.sign(format!("{timestamp}{body}").as_bytes()).to_bytes());
+
+	tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.unwrap()
+		.block_on(async {
+			let server = InteractionServer::bind("127.0.0.1:0", &public_key).await.unwrap();
+			let client = Client::builder(TokioExecutor::new()).build_http::<Full<Bytes>>();
+			let post = |body: &'static str, timestamp: &str, signature: String| {
+				let request = Request::post(format!("http://{}/", server.address))
+					.header("X-Signature-Ed25519", signature)
+					.header("X-Signature-Timestamp", timestamp)
+					.body(Full::new(Bytes::from(body)))
+					.unwrap();
+				let response = client.request(request);
+				async move {
+					let response = response.await.unwrap();
+					let status = response.status();
+					(status, response.into_body().collect().await.unwrap().to_bytes())
+				}
+			};
+
+			let now = chrono::Utc::now().timestamp().to_string();
+			// The ping discord sends to verify the endpoint, which has no channel
+			let ping =
+				r#"{"id":"1","application_id":"2","type":1,"token":"t","version":1,"user":{"id":"5","username":"discord","discriminator":"0"}}"#;
+			let (status, body) = post(ping, &now, signed(ping, &now)).await;
+			assert_eq!((status, &body[..]), (StatusCode::OK, &br#"{"type":1}"#[..]));
+
+			let (status, _) = post(ping, &now, signed(ping, "1700000001")).await;
+			assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+			// A correctly signed request from long ago could have been captured and replayed
+			let (status, body) = post(ping, "1700000000", signed(ping, "1700000000")).await;
+			assert_eq!((status, &body[..]), (StatusCode::UNAUTHORIZED, &b"request timestamp too old"[..]));
+
+			let command = r#"{"id":"4","application_id":"2","type":2,"channel_id":"3","token":"t","data":{"name":"about"}}"#;
+			let (status, _) = post(command, &now, signed(command, &now)).await;
+			assert_eq!(status, StatusCode::ACCEPTED);
+			assert_eq!(server.next().await.unwrap().id, "4");
+		});
+}
@@ -1,21 +1,43 @@
 #[macro_use]
 extern crate proc_macros;
+// The derived slash commands refer to `discord::...`, which also needs to work inside this crate
+extern crate self as discord;
 
 #[macro_use]
 extern crate log;
 
+mod cache;
+mod rate_limit;
 mod requests;
+mod session;
+mod shard;
 mod websocket_handle;
 
+#[cfg(feature = "mock")]
+pub mod mock;
+
+#[cfg(feature = "http-interactions")]
+pub mod interactions_server;
+
+pub mod slash_command;
+
 pub use websocket_handle::Connection;
 
 pub use websocket_handle::Read;
+pub use websocket_handle::ZlibStream;
 
 mod discord_structs;
 pub use discord_structs::*;
 
+pub use cache::{Cache, CachedGuild};
+pub use proc_macros::{CommandList, SlashCommand};
 pub use requests::DiscordClient;
+pub use requests::FileUpload;
 pub use requests::NetError;
+pub use requests::ScopedHeader;
+pub use session::GatewaySession;
+pub use shard::{ShardEvent, ShardManager};
+pub use slash_command::{CommandAutocomplete, CommandChoice, CommandList, CommandOption, SlashCommand};
 
 pub use futures_util::StreamExt;
 pub extern crate serde_json;
@@ -0,0 +1,320 @@
+//! A local fake discord (rest api and gateway) for testing a bot from start to finish without the real service
+//!
+//! Enabled with the `mock` feature. The gateway sends `Hello` on connect, `Ready` after an identify and acknowledges heartbeats,
+//! everything else is scripted by the test with [`MockDiscord::dispatch`].
+
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_channel::Sender;
+use futures_util::{SinkExt, StreamExt};
+use http_body_util::BodyExt;
+use hyper::body::Incoming;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde_json::{json, Value};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::DiscordClient;
+
+/// A request that was sent to the mock rest api
+#[derive(Clone, Debug)]
+pub struct MockRequest {
+	pub method: Method,
+	/// The path without the api prefix, e.g. `/channels/1/messages`
+	pub path: String,
+	/// The query string, e.g. `limit=50`
+	pub query: Option<String>,
+	pub headers: hyper::HeaderMap,
+	pub body: Value,
+}
+
+/// A response that the test has set up, with the headers to send
+type MockResponse = (StatusCode, hyper::HeaderMap, Value);
+
+#[derive(Default)]
+struct MockState {
+	requests: Vec<MockRequest>,
+	gateway_received: Vec<Value>,
+	responses: HashMap<(Method, String), (StatusCode, Value)>,
+	/// Responses with headers that are used once, before the ones in `responses`
+	queued: HashMap<(Method, String), VecDeque<MockResponse>>,
+	connection: Option<Sender<Message>>,
+	sequence: usize,
+	next_id: u64,
+}
+
+/// A running fake discord server
+#[derive(Clone)]
+pub struct MockDiscord {
+	state: Arc<Mutex<MockState>>,
+	/// The base url of the rest api
+	pub api: String,
+	/// The address of the gateway
+	pub gateway: String,
+}
+
+impl MockDiscord {
+	/// The prefix of the rest api paths
+	const API_PATH: &'static str = "/api/v10";
+	/// How long [`MockDiscord::wait_until`] waits before failing
+	const TIMEOUT: Duration = Duration::from_secs(5);
+
+	/// Starts the rest api and gateway on local ports
+	pub async fn start() -> Self {
+		let rest = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let gateway = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let mock = Self {
+			state: Default::default(),
+			api: format!("http://{}{}", rest.local_addr().unwrap(), Self::API_PATH),
+			gateway: format!("ws://{}", gateway.local_addr().unwrap()),
+		};
+
+		let state = mock.state.clone();
+		let gateway_url = mock.gateway.clone();
+		tokio::spawn(async move {
+			while let Ok((stream, _)) = rest.accept().await {
+				tokio::spawn(rest_connection(state.clone(), gateway_url.clone(), stream));
+			}
+		});
+		let state = mock.state.clone();
+		let gateway_url = mock.gateway.clone();
+		tokio::spawn(async move {
+			while let Ok((stream, _)) = gateway.accept().await {
+				tokio::spawn(gateway_connection(state.clone(), gateway_url.clone(), stream));
+			}
+		});
+
+		mock
+	}
+
+	/// Creates a client that talks to this server
+	pub fn client(&self, token: &'static str) -> DiscordClient {
+		DiscordClient::new(token).with_api(&self.api).with_gateway(&self.gateway)
+	}
+
+	/// Sets the response to a specific request (instead of the default of echoing the body back with an id)
+	pub fn respond(&self, method: Method, path: impl Into<String>, status: StatusCode, body: Value) {
+		self.state.lock().unwrap().responses.insert((method, path.into()), (status, body));
+	}
+
+	/// Queues a response with headers (e.g. rate limits) for the next matching request, after which the usual response is used
+	pub fn respond_once(&self, method: Method, path: impl Into<String>, status: StatusCode, headers: &[(&'static str, &str)], body: Value) {
+		let headers = headers
+			.iter()
+			.map(|&(name, value)| (hyper::header::HeaderName::from_static(name), value.parse().unwrap()))
+			.collect();
+		let mut state = self.state.lock().unwrap();
+		state.queued.entry((method, path.into())).or_default().push_back((status, headers, body));
+	}
+
+	/// All the requests sent to the rest api
+	pub fn requests(&self) -> Vec<MockRequest> {
+		self.state.lock().unwrap().requests.clone()
+	}
+
+	/// The bodies of all interaction callbacks
+	pub fn interaction_callbacks(&self) -> Vec<Value> {
+		self.requests()
+			.into_iter()
+			.filter(|request| request.path.starts_with("/interactions/") && request.path.ends_with("/callback"))
+			.map(|request| request.body)
+			.collect()
+	}
+
+	/// The channel ids and bodies of all messages posted to channels
+	pub fn channel_messages(&self) -> Vec<(String, Value)> {
+		self.requests()
+			.into_iter()
+			.filter(|request| request.method == Method::POST)
+			.filter_map(|request| {
+				let channel = request.path.strip_prefix("/channels/")?.strip_suffix("/messages")?;
+				Some((channel.to_string(), request.body))
+			})
+			.collect()
+	}
+
+	/// All the payloads received by the gateway
+	pub fn gateway_received(&self) -> Vec<Value> {
+		self.state.lock().unwrap().gateway_received.clone()
+	}
+
+	/// Sends a dispatch with the event name (e.g. `INTERACTION_CREATE`) and data to the connected gateway client
+	pub async fn dispatch(&self, event: &str, d: Value) {
+		let (connection, message) = {
+			let mut state = self.state.lock().unwrap();
+			(state.connection.clone(), dispatch_message(&mut state, event, d))
+		};
+		connection.expect("No gateway connection").send(message).await.unwrap();
+	}
+
+	/// Closes the gateway connection with the specified close code
+	pub async fn close_gateway(&self, code: u16) {
+		let connection = self.state.lock().unwrap().connection.take();
+		let frame = CloseFrame {
+			code: CloseCode::from(code),
+			reason: "Mock closed".into(),
+		};
+		connection
+			.expect("No gateway connection")
+			.send(Message::Close(Some(frame)))
+			.await
+			.unwrap();
+	}
+
+	/// Waits until the condition is true, panicking if it takes too long
+	pub async fn wait_until(&self, condition: impl Fn(&MockDiscord) -> bool) {
+		let start = tokio::time::Instant::now();
+		while !condition(self) {
+			assert!(start.elapsed() < Self::TIMEOUT, "Timed out waiting for the mock discord");
+			tokio::time::sleep(Duration::from_millis(10)).await;
+		}
+	}
+}
+
+/// Creates a dispatch with the next sequence number
+fn dispatch_message(state: &mut MockState, event: &str, d: Value) -> Message {
+	state.sequence += 1;
+	Message::Text(json!({"op": 0, "t": event, "s": state.sequence, "d": d}).to_string())
+}
+
+/// Handles a websocket connection to the gateway
+async fn gateway_connection(state: Arc<Mutex<MockState>>, gateway_url: String, stream: TcpStream) {
+	let Ok(socket) = tokio_tungstenite::accept_async(stream).await else {
+		return;
+	};
+	let (mut write, mut read) = socket.split();
+
+	let (send, receive) = async_channel::unbounded();
+	state.lock().unwrap().connection = Some(send.clone());
+	tokio::spawn(async move {
+		while let Ok(message) = receive.recv().await {
+			let close = matches!(message, Message::Close(_));
+			if write.send(message).await.is_err() || close {
+				return;
+			}
+		}
+	});
+
+	let hello = json!({"op": 10, "d": {"heartbeat_interval": 41250}});
+	let _ = send.send(Message::Text(hello.to_string())).await;
+
+	while let Some(Ok(Message::Text(text))) = read.next().await {
+		let Ok(payload) = serde_json::from_str::<Value>(&text) else {
+			continue;
+		};
+		let op = payload["op"].as_u64();
+		let reply = {
+			let mut state = state.lock().unwrap();
+			state.gateway_received.push(payload);
+			match op {
+				Some(1) => Some(Message::Text(json!({"op": 11}).to_string())),
+				Some(2) => {
+					let ready = json!({
+						"v": 10,
+						"session_id": "mock-session",
+						"resume_gateway_url": gateway_url,
+						"application": {"id": "mock-application"}
+					});
+					Some(dispatch_message(&mut state, "READY", ready))
+				}
+				Some(6) => Some(dispatch_message(&mut state, "RESUMED", Value::Null)),
+				_ => None,
+			}
+		};
+		if let Some(reply) = reply {
+			let _ = send.send(reply).await;
+		}
+	}
+}
+
+/// Handles a http connection to the rest api
+async fn rest_connection(state: Arc<Mutex<MockState>>, gateway_url: String, stream: TcpStream) {
+	let service = service_fn(move |request| rest_request(state.clone(), gateway_url.clone(), request));
+	if let Err(e) = hyper::server::conn::http1::Builder::new()
+		.serve_connection(TokioIo::new(stream), service)
+		.await
+	{
+		debug!("Mock rest connection failed {e:?}");
+	}
+}
+
+/// Records a request to the rest api and responds with the specified response or a default
+async fn rest_request(state: Arc<Mutex<MockState>>, gateway_url: String, request: Request<Incoming>) -> Result<Response<String>, Infallible> {
+	let method = request.method().clone();
+	let headers = request.headers().clone();
+	let query = request.uri().query().map(str::to_string);
+	let path = request.uri().path();
+	let path = path.strip_prefix(MockDiscord::API_PATH).unwrap_or(path).to_string();
+	let body = request.into_body().collect().await.map(|body| body.to_bytes()).unwrap_or_default();
+	let body = serde_json::from_slice::<Value>(multipart_payload(&body).unwrap_or(&body)).unwrap_or(Value::Null);
+
+	let mut state = state.lock().unwrap();
+	state.requests.push(MockRequest {
+		method: method.clone(),
+		path: path.clone(),
+		query,
+		headers,
+		body: body.clone(),
+	});
+
+	let key = (method.clone(), path.clone());
+	if let Some((status, headers, body)) = state.queued.get_mut(&key).and_then(VecDeque::pop_front) {
+		let mut response = Response::builder().status(status).body(body.to_string()).unwrap();
+		response.headers_mut().extend(headers);
+		return Ok(response);
+	}
+	let (status, body) = match state.responses.get(&key) {
+		Some(response) => response.clone(),
+		None if method == Method::GET && path == "/gateway/bot" => {
+			let limit = json!({"total": 1000, "remaining": 1000, "reset_after": 0, "max_concurrency": 1});
+			(StatusCode::OK, json!({"url": gateway_url, "shards": 1, "session_start_limit": limit}))
+		}
+		None if method == Method::GET => (StatusCode::NOT_FOUND, json!({"code": 0, "message": "404: Not Found (mock)"})),
+		None if method == Method::DELETE => (StatusCode::NO_CONTENT, Value::Null),
+		// Echo the body back as if it was created with a new id
+		None => {
+			let mut body = body;
+			if let Value::Object(object) = &mut body {
+				state.next_id += 1;
+				object.entry("id").or_insert_with(|| state.next_id.to_string().into());
+				if let Some(channel) = message_channel(&path) {
+					let author = json!({"id": "mock-application", "username": "Mock Bot", "discriminator": "0"});
+					object.insert("channel_id".into(), channel.into());
+					object.insert("author".into(), author);
+					object.insert("timestamp".into(), chrono::Utc::now().to_rfc3339().into());
+					object.entry("content").or_insert_with(|| "".into());
+				}
+			}
+			(StatusCode::OK, body)
+		}
+	};
+
+	let body = if body.is_null() { String::new() } else { body.to_string() };
+	Ok(Response::builder().status(status).body(body).unwrap())
+}
+
+/// The channel id if the path creates or edits a message (in a channel or from an interaction webhook)
+fn message_channel(path: &str) -> Option<&str> {
+	let mut segments = path.split('/').skip(1);
+	match (segments.next()?, segments.next()?, segments.next()) {
+		("channels", channel, Some("messages")) => Some(channel),
+		("webhooks", ..) => Some("mock-channel"),
+		_ => None,
+	}
+}
+
+/// Finds the json payload in a `multipart/form-data` body (used for uploading files)
+fn multipart_payload(body: &[u8]) -> Option<&[u8]> {
+	let find = |data: &[u8], pattern: &[u8]| data.windows(pattern.len()).position(|window| window == pattern);
+	let part = find(body, br#"name="payload_json""#)?;
+	let start = part + find(&body[part..], b"\r\n\r\n")? + 4;
+	let end = start + find(&body[start..], b"\r\n--")?;
+	Some(&body[start..end])
+}
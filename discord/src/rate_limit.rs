@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use hyper::{HeaderMap, Method};
+use tokio::time::Instant;
+
+/// The state of a single rate limit bucket
+#[derive(Debug)]
+struct Bucket {
+	limit: u64,
+	remaining: u64,
+	reset: Instant,
+}
+
+/// Keeps track of discord's rate limits so requests wait instead of getting a 429
+///
+/// https://discord.com/developers/docs/topics/rate-limits
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+	/// The bucket hash that discord has told us each route uses
+	routes: HashMap<String, String>,
+	/// The buckets (keyed by bucket hash and major parameter)
+	buckets: HashMap<String, Bucket>,
+	/// When the global rate limit is over (if we have hit it)
+	global_reset: Option<Instant>,
+}
+
+impl RateLimiter {
+	/// Finds the route of a request: the method and path with all ids except the major parameters (channel, guild and webhook) removed
+	pub fn route(method: &Method, uri: &str) -> String {
+		let path = uri.parse::<hyper::Uri>().map_or_else(|_| uri.to_string(), |uri| uri.path().to_string());
+		let segments = path.split('/').filter(|segment| !segment.is_empty()).collect::<Vec<_>>();
+		let mut route = format!("{method} ");
+		for (index, segment) in segments.iter().enumerate() {
+			let previous = |n: usize| index.checked_sub(n).map_or("", |index| segments[index]);
+			let is_id = segment.chars().all(|c| c.is_ascii_digit());
+			let is_major = matches!(previous(1), "channels" | "guilds" | "webhooks");
+			// Interaction tokens are unique to each interaction so they are removed too (including in followup webhooks)
+			let is_interaction = previous(1) == "interactions" || previous(2) == "interactions";
+			let is_webhook_token = previous(2) == "webhooks";
+
+			route.push('/');
+			if (is_id && !is_major) || is_interaction || is_webhook_token {
+				route.push_str("{id}");
+			} else {
+				route.push_str(segment);
+			}
+		}
+		route
+	}
+
+	/// The key of the bucket for a route, if discord has told us which bucket it is in
+	///
+	/// The only ids left in a route are the major parameters, which each have their own bucket.
+	fn bucket_key(&self, route: &str) -> Option<String> {
+		let major_parameters = route.split('/').filter(|segment| segment.chars().all(|c| c.is_ascii_digit()));
+		let major_parameters = major_parameters.collect::<Vec<_>>().join(":");
+		self.routes.get(route).map(|hash| format!("{hash}:{major_parameters}"))
+	}
+
+	/// Waits until a request can be sent on the specified route without hitting a rate limit
+	pub async fn acquire(&mut self, route: &str) {
+		if let Some(reset) = self.global_reset.filter(|reset| *reset > Instant::now()) {
+			warn!("Waiting {:?} for the global rate limit", reset - Instant::now());
+			tokio::time::sleep_until(reset).await;
+		}
+		let Some(bucket) = self.bucket_key(route).and_then(|key| self.buckets.get_mut(&key)) else {
+			return;
+		};
+		if bucket.reset <= Instant::now() {
+			bucket.remaining = bucket.limit;
+		} else if bucket.remaining == 0 {
+			warn!("Waiting {:?} for the rate limit on {route}", bucket.reset - Instant::now());
+			tokio::time::sleep_until(bucket.reset).await;
+			bucket.remaining = bucket.limit;
+		}
+		bucket.remaining = bucket.remaining.saturating_sub(1);
+	}
+
+	/// Updates the rate limits from the headers of a response, returning how long to wait before retrying if the request was rate limited
+	pub fn update(&mut self, route: &str, status: hyper::StatusCode, headers: &HeaderMap) -> Option<Duration> {
+		let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+		let seconds = |name: &str| header(name).and_then(|value| value.parse::<f64>().ok()).map(Duration::from_secs_f64);
+
+		if let Some(hash) = header("x-ratelimit-bucket") {
+			self.routes.insert(route.to_string(), hash.to_string());
+		}
+		if let (Some(key), Some(limit), Some(remaining), Some(reset_after)) = (
+			self.bucket_key(route),
+			header("x-ratelimit-limit").and_then(|value| value.parse().ok()),
+			header("x-ratelimit-remaining").and_then(|value| value.parse().ok()),
+			seconds("x-ratelimit-reset-after"),
+		) {
+			let reset = Instant::now() + reset_after;
+			self.buckets.insert(key, Bucket { limit, remaining, reset });
+		}
+
+		if status != hyper::StatusCode::TOO_MANY_REQUESTS {
+			return None;
+		}
+		let retry_after = seconds("retry-after").unwrap_or(Duration::from_secs(1));
+		if header("x-ratelimit-global") == Some("true") {
+			self.global_reset = Some(Instant::now() + retry_after);
+		}
+		warn!(
+			"Rate limited on {route} ({:?} scope), retrying after {retry_after:?}",
+			header("x-ratelimit-scope")
+		);
+		Some(retry_after)
+	}
+}
+
+#[test]
+fn rate_limit_routes() {
+	let route = |method, uri| RateLimiter::route(&method, uri);
+	assert_eq!(
+		route(Method::POST, "https://discord.com/api/v10/channels/1018447658685321266/messages"),
+		"POST /api/v10/channels/1018447658685321266/messages"
+	);
+	assert_eq!(
+		route(
+			Method::PUT,
+			"https://discord.com/api/v10/guilds/907657508292792342/members/630073509137350690/roles/985630968650010705"
+		),
+		"PUT /api/v10/guilds/907657508292792342/members/{id}/roles/{id}"
+	);
+	assert_eq!(
+		route(
+			Method::POST,
+			"https://discord.com/api/v10/interactions/970075279823360010/aW50ZXJhY3Rpb24/callback"
+		),
+		"POST /api/v10/interactions/{id}/{id}/callback"
+	);
+	assert_eq!(
+		route(
+			Method::PATCH,
+			"https://discord.com/api/v10/webhooks/970075279823360010/aW50ZXJhY3Rpb24/messages/@original"
+		),
+		"PATCH /api/v10/webhooks/970075279823360010/{id}/messages/@original"
+	);
+}
+
+#[cfg(test)]
+fn rate_limit_headers(headers: &[(&'static str, &str)]) -> HeaderMap {
+	headers
+		.iter()
+		.map(|&(name, value)| (hyper::header::HeaderName::from_static(name), value.parse().unwrap()))
+		.collect()
+}
+
+#[test]
+fn rate_limit_buckets() {
+	tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.unwrap()
+		.block_on(async {
+			let mut limiter = RateLimiter::default();
+			let route = "POST /api/v10/channels/1/messages";
+			let other_channel = "POST /api/v10/channels/2/messages";
+			// Unknown routes are not limited until discord tells us their bucket
+			let start = Instant::now();
+			limiter.acquire(route).await;
+			assert!(start.elapsed() < Duration::from_millis(100));
+
+			let headers = rate_limit_headers(&[
+				("x-ratelimit-bucket", "abc"),
+				("x-ratelimit-limit", "5"),
+				("x-ratelimit-remaining", "0"),
+				("x-ratelimit-reset-after", "0.3"),
+			]);
+			assert_eq!(limiter.update(route, hyper::StatusCode::OK, &headers), None);
+			limiter.update(
+				other_channel,
+				hyper::StatusCode::OK,
+				&rate_limit_headers(&[("x-ratelimit-bucket", "abc")]),
+			);
+
+			// Each major parameter has its own bucket
+			let start = Instant::now();
+			limiter.acquire(other_channel).await;
+			assert!(start.elapsed() < Duration::from_millis(100));
+			limiter.acquire(route).await;
+			assert!(start.elapsed() >= Duration::from_millis(300));
+			// The bucket has been reset so there is no wait
+			let start = Instant::now();
+			limiter.acquire(route).await;
+			assert!(start.elapsed() < Duration::from_millis(100));
+		});
+}
+
+#[test]
+fn rate_limit_global() {
+	tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.unwrap()
+		.block_on(async {
+			let mut limiter = RateLimiter::default();
+			let headers = rate_limit_headers(&[("retry-after", "0.3"), ("x-ratelimit-global", "true"), ("x-ratelimit-scope", "global")]);
+			let retry_after = limiter.update("POST /api/v10/channels/1/messages", hyper::StatusCode::TOO_MANY_REQUESTS, &headers);
+			assert_eq!(retry_after, Some(Duration::from_millis(300)));
+
+			// Every route waits for the global limit
+			let start = Instant::now();
+			limiter.acquire("GET /api/v10/guilds/2/roles").await;
+			assert!(start.elapsed() >= Duration::from_millis(300));
+		});
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn rate_limit_retry() {
+	use hyper::{Method, StatusCode};
+	use serde_json::json;
+	tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.unwrap()
+		.block_on(async {
+			let mock = crate::mock::MockDiscord::start().await;
+			let mut client = mock.client("token");
+			let limited = json!({"message": "You are being rate limited.", "retry_after": 0.3, "global": false});
+			let headers = [("retry-after", "0.3"), ("x-ratelimit-scope", "user")];
+			mock.respond_once(Method::POST, "/channels/1/messages", StatusCode::TOO_MANY_REQUESTS, &headers, limited);
+
+			let start = Instant::now();
+			let message = crate::ChannelMessage::new().with_content("hi").post_create(&mut client, 1).await.unwrap();
+			assert!(start.elapsed() >= Duration::from_millis(300));
+			assert_eq!(message.content, "hi");
+			assert_eq!(mock.channel_messages().len(), 2);
+		});
+}
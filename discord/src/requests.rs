@@ -1,10 +1,15 @@
 use std::string::FromUtf8Error;
 
 use super::websocket_handle;
-use http_body_util::BodyExt;
-use hyper::header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE};
+use crate::cache::Cache;
+use crate::rate_limit::RateLimiter;
+use crate::{Channel, Dispatch, GuildMember, Role};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE};
 use hyper::{Method, Request};
 use hyper_util::rt::TokioExecutor;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 
 #[derive(std::fmt::Debug)]
 pub enum NetError {
@@ -12,65 +17,330 @@ pub enum NetError {
 	HyperHttp(hyper::http::Error),
 	Utf8(FromUtf8Error),
 	DeJson(serde_json::Error, String),
+	/// Discord responded with an unsuccessful status (https://discord.com/developers/docs/reference#error-messages)
+	Api {
+		status: hyper::StatusCode,
+		/// The JSON error code (https://discord.com/developers/docs/topics/opcodes-and-status-codes#json)
+		code: Option<u64>,
+		message: String,
+		/// Details of which fields were invalid
+		errors: Option<serde_json::Value>,
+	},
+}
+
+impl NetError {
+	/// The JSON error code discord sends when a user can't be sent a dm (they have closed dms or blocked the bot)
+	pub const CANNOT_MESSAGE_USER: u64 = 50007;
+
+	/// Checks if the request might succeed if sent again later, without the risk of it being handled twice
+	///
+	/// This is a failure to connect (before anything was sent), a rate limit or a server error on discord's side.
+	/// Other errors (such as the connection dropping while waiting for the response) could happen after discord has handled the request.
+	pub fn is_retryable(&self) -> bool {
+		match self {
+			NetError::Hyper(e) => e.is_connect(),
+			NetError::Api { status, .. } => matches!(status.as_u16(), 429 | 500..=599),
+			_ => false,
+		}
+	}
+
+	/// Creates the error for an unsuccessful response from the body, which is json with the error code unless it came from a proxy
+	pub fn from_response(status: hyper::StatusCode, body: String) -> Self {
+		let ApiErrorBody { code, message, errors } = serde_json::from_str(&body).unwrap_or(ApiErrorBody {
+			code: None,
+			message: body,
+			errors: None,
+		});
+		NetError::Api {
+			status,
+			code,
+			message,
+			errors,
+		}
+	}
+
+	/// Checks if the request failed because the user does not accept dms from the bot
+	pub fn is_dm_closed(&self) -> bool {
+		matches!(
+			self,
+			NetError::Api {
+				code: Some(Self::CANNOT_MESSAGE_USER),
+				..
+			}
+		)
+	}
+}
+
+/// The body discord sends with an unsuccessful response
+#[derive(serde::Deserialize)]
+struct ApiErrorBody {
+	code: Option<u64>,
+	message: String,
+	errors: Option<serde_json::Value>,
+}
+
+/// A file to upload with a request (see the `_with_files` requests)
+///
+/// https://discord.com/developers/docs/reference#uploading-files
+#[derive(Clone, Debug)]
+pub struct FileUpload {
+	pub filename: String,
+	/// The MIME type of the file, e.g. `text/csv`
+	pub content_type: String,
+	pub data: Vec<u8>,
+}
+
+impl FileUpload {
+	pub fn new(filename: impl Into<String>, content_type: impl Into<String>, data: impl Into<Vec<u8>>) -> Self {
+		Self {
+			filename: filename.into(),
+			content_type: content_type.into(),
+			data: data.into(),
+		}
+	}
+}
+
+/// Builds a `multipart/form-data` body with the json payload and files, returning the content type (with the boundary) and body
+fn multipart_body(json: &str, files: &[FileUpload]) -> (String, Vec<u8>) {
+	let boundary = format!("cheese-boundary-{:016x}", rand::random::<u64>());
+	let mut body = Vec::new();
+	let mut part = |disposition: String, content_type: &str, data: &[u8]| {
+		body.extend_from_slice(format!("--{boundary}\r\nContent-Disposition: form-data; {disposition}\r\n").as_bytes());
+		body.extend_from_slice(format!("Content-Type: {content_type}\r\n\r\n").as_bytes());
+		body.extend_from_slice(data);
+		body.extend_from_slice(b"\r\n");
+	};
+
+	part(r#"name="payload_json""#.to_string(), "application/json", json.as_bytes());
+	for (index, file) in files.iter().enumerate() {
+		let filename = file.filename.replace(['"', '\r', '\n'], "_");
+		part(format!(r#"name="files[{index}]"; filename="{filename}""#), &file.content_type, &file.data);
+	}
+	body.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+
+	(format!("multipart/form-data; boundary={boundary}"), body)
 }
 
 pub struct DiscordClient {
 	pub token: String,
-	client: hyper_util::client::legacy::Client<hyper_rustls::HttpsConnector<hyper_util::client::legacy::connect::HttpConnector>, String>,
+	/// The base url of the rest api (defaults to [`DiscordClient::API`])
+	pub api: String,
+	/// The gateway address to connect to instead of the one from `/gateway/bot`
+	pub gateway: Option<String>,
+	/// The guilds, roles, members and channels received on the gateway (see [`DiscordClient::with_cache`])
+	pub cache: Option<Cache>,
+	/// If the gateway connection should be compressed (see [`DiscordClient::with_compression`])
+	pub compress: bool,
+	client: hyper_util::client::legacy::Client<hyper_rustls::HttpsConnector<hyper_util::client::legacy::connect::HttpConnector>, Full<Bytes>>,
+	rate_limiter: RateLimiter,
+	/// Extra headers sent with every request (see [`DiscordClient::scoped_header`])
+	headers: HeaderMap,
 }
 impl DiscordClient {
 	pub const API: &'static str = "https://discord.com/api/v10";
 	pub const GUILD_ID: &'static str = "907657508292792342";
+	/// The number of times a request is retried after being rate limited
+	pub const MAX_RETRIES: usize = 5;
 
 	/// Constructs a new client
 	pub fn new(token: &'static str) -> Self {
+		// Plain http is allowed so that the client can be pointed at a local server (see [`DiscordClient::with_api`])
 		let https = hyper_rustls::HttpsConnectorBuilder::new()
 			.with_native_roots()
 			.unwrap()
-			.https_only()
+			.https_or_http()
 			.enable_http1()
 			.build();
 		Self {
 			token: token.split_ascii_whitespace().collect(),
+			api: Self::API.to_string(),
+			gateway: None,
+			cache: None,
+			compress: false,
 			client: hyper_util::client::legacy::Client::builder(TokioExecutor::new()).build(https),
+			rate_limiter: RateLimiter::default(),
+			headers: HeaderMap::new(),
 		}
 	}
 
-	/// Makes the specified request
+	/// Sets the base url of the rest api, e.g. `http://127.0.0.1:8080/api/v10` for a local mock server (builder pattern)
+	pub fn with_api(mut self, api: impl Into<String>) -> Self {
+		self.api = api.into();
+		self
+	}
+
+	/// Sets the gateway address to connect to instead of asking discord, e.g. `ws://127.0.0.1:8081` (builder pattern)
+	pub fn with_gateway(mut self, gateway: impl Into<String>) -> Self {
+		self.gateway = Some(gateway.into());
+		self
+	}
+
+	/// Keeps a cache of guilds which is used before making requests for members, roles and channels (builder pattern)
+	///
+	/// The cache is only filled if every dispatch is passed to [`DiscordClient::observe`].
+	pub fn with_cache(mut self) -> Self {
+		self.cache = Some(Cache::default());
+		self
+	}
+
+	/// Compresses messages from the gateway with `zlib-stream` (builder pattern)
+	pub fn with_compression(mut self) -> Self {
+		self.compress = true;
+		self
+	}
+
+	/// Sends the header with every request until the returned guard is dropped
+	///
+	/// The guard can be used in place of the client, e.g. `Role::new().post_create_role(&mut client.audit_log_reason("..."), guild_id)`
+	/// Scopes can be nested, the header from the outer scope is restored when an inner one ends.
+	pub fn scoped_header(&mut self, name: HeaderName, value: HeaderValue) -> ScopedHeader<'_> {
+		let previous = self.headers.insert(name.clone(), value);
+		ScopedHeader {
+			client: self,
+			name,
+			previous,
+		}
+	}
+
+	/// Records the reason in the server audit log for the requests made until the returned guard is dropped
+	///
+	/// https://discord.com/developers/docs/resources/audit-log#audit-log-entry-object-optional-audit-entry-info
+	pub fn audit_log_reason(&mut self, reason: impl std::fmt::Display) -> ScopedHeader<'_> {
+		// Reasons can be at most 512 characters and are url encoded to allow unicode
+		let reason = reason.to_string().chars().take(512).collect::<String>();
+		let value = HeaderValue::from_str(&Self::url_encode(reason)).unwrap();
+		self.scoped_header(HeaderName::from_static("x-audit-log-reason"), value)
+	}
+
+	/// Percent encodes a path segment or query value (everything except letters, digits and `-._~`)
+	pub fn url_encode(value: impl std::fmt::Display) -> String {
+		const RESERVED: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+		utf8_percent_encode(&value.to_string(), RESERVED).to_string()
+	}
+
+	/// The address of the gateway, from `/gateway/bot` unless it has been overridden
+	pub async fn gateway_url(&mut self) -> Result<String, NetError> {
+		if let Some(gateway) = &self.gateway {
+			return Ok(gateway.clone());
+		}
+		let gateway = crate::GatewayMeta::get_gateway_meta(self).await?;
+		info!("received gateway metadata: {:?}", gateway);
+		Ok(gateway.url)
+	}
+
+	/// Updates the cache (if enabled) from a dispatch received on the gateway
+	pub fn observe(&mut self, dispatch: &Dispatch) {
+		if let Some(cache) = &mut self.cache {
+			cache.observe(dispatch);
+		}
+	}
+
+	/// Gets a member from the cache, or from discord if they are not cached or the guild has not been received (caching the result)
+	pub async fn guild_member(&mut self, guild_id: &str, user_id: &str) -> Result<GuildMember, NetError> {
+		if let Some(member) = self.cache.as_ref().and_then(|cache| cache.member(guild_id, user_id)) {
+			return Ok(member.clone());
+		}
+		let member = GuildMember::get_get_guild_member(self, guild_id, user_id).await?;
+		if let Some(cache) = &mut self.cache {
+			cache.insert_member(guild_id, member.clone());
+		}
+		Ok(member)
+	}
+
+	/// Gets the roles of a guild from the cache, or from discord if `GUILD_CREATE` has not been received for the guild
+	pub async fn guild_roles(&mut self, guild_id: &str) -> Result<Vec<Role>, NetError> {
+		if let Some(roles) = self.cache.as_ref().and_then(|cache| cache.roles(guild_id)) {
+			return Ok(roles);
+		}
+		let roles = Role::get_guild_roles(self, guild_id).await?;
+		if let Some(cache) = &mut self.cache {
+			for role in &roles {
+				cache.insert_role(guild_id, role.clone());
+			}
+		}
+		Ok(roles)
+	}
+
+	/// Gets a channel from the cache, or from discord if it is not cached (caching the result)
+	pub async fn channel(&mut self, channel_id: &str) -> Result<Channel, NetError> {
+		if let Some(channel) = self.cache.as_ref().and_then(|cache| cache.channel(channel_id)) {
+			return Ok(channel.clone());
+		}
+		let channel = Channel::get_channel(self, channel_id).await?;
+		if let Some(cache) = &mut self.cache {
+			cache.insert_channel(channel.clone());
+		}
+		Ok(channel)
+	}
+
+	/// Makes the specified request with a json body
+	///
+	/// Waits for any rate limits on the route and retries after being rate limited, so requests are queued rather than rejected.
 	pub async fn request<'a>(&'a mut self, uri: &'a str, body: String, method: Method) -> Result<String, NetError> {
 		debug!("Sending {:?} to {} {}", method, &uri, &body);
+		let content_type = HeaderValue::from_static("application/json");
+		self.send(uri, Bytes::from(body.clone()), content_type, method, &body).await
+	}
 
-		let now = tokio::time::Instant::now();
-		let mut req = Request::builder()
-			.method(&method)
-			.uri(uri)
-			.body(body.clone())
-			.map_err(NetError::HyperHttp)?;
+	/// Makes the specified request with a json body and files as `multipart/form-data`
+	pub async fn request_multipart<'a>(&'a mut self, uri: &'a str, body: String, files: &[FileUpload], method: Method) -> Result<String, NetError> {
+		let filenames = files.iter().map(|file| file.filename.as_str()).collect::<Vec<_>>();
+		debug!("Sending {:?} to {} {} with files {:?}", method, &uri, &body, filenames);
+		let (content_type, multipart) = multipart_body(&body, files);
+		let content_type = HeaderValue::from_str(&content_type).unwrap();
+		self.send(uri, Bytes::from(multipart), content_type, method, &body).await
+	}
 
-		req.headers_mut()
-			.insert(AUTHORIZATION, HeaderValue::from_str(&format!("Bot {}", self.token)).unwrap());
+	/// Sends a request (the description of the body is used for logging errors)
+	async fn send(&mut self, uri: &str, body: Bytes, content_type: HeaderValue, method: Method, description: &str) -> Result<String, NetError> {
+		let route = RateLimiter::route(&method, uri);
+		let mut attempt = 0;
+		let (status, utf) = loop {
+			self.rate_limiter.acquire(&route).await;
 
-		req.headers_mut().insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+			let now = tokio::time::Instant::now();
+			let mut req = Request::builder()
+				.method(&method)
+				.uri(uri)
+				.body(Full::new(body.clone()))
+				.map_err(NetError::HyperHttp)?;
 
-		let res = self.client.request(req).await.map_err(NetError::Hyper)?;
+			req.headers_mut()
+				.insert(AUTHORIZATION, HeaderValue::from_str(&format!("Bot {}", self.token)).unwrap());
 
-		// And then, if the request gets a response...
-		let status = res.status();
-		debug!("received {} in {}ms", status, now.elapsed().as_millis());
+			req.headers_mut().insert(CONTENT_TYPE, content_type.clone());
+			req.headers_mut().extend(self.headers.clone());
 
-		// Concatenate the body stream into a single buffer...
+			let res = self.client.request(req).await.map_err(NetError::Hyper)?;
 
-		let x = res.collect().await.unwrap();
-		let bytes = x.to_bytes();
+			// And then, if the request gets a response...
+			let status = res.status();
+			debug!("received {} in {}ms", status, now.elapsed().as_millis());
+			let retry_after = self.rate_limiter.update(&route, status, res.headers());
 
-		let utf = String::from_utf8(bytes.to_vec()).map_err(NetError::Utf8)?;
+			// Concatenate the body stream into a single buffer...
+
+			let x = res.collect().await.unwrap();
+			let bytes = x.to_bytes();
+
+			let utf = String::from_utf8(bytes.to_vec()).map_err(NetError::Utf8)?;
+
+			match retry_after {
+				Some(retry_after) if attempt < Self::MAX_RETRIES => {
+					attempt += 1;
+					tokio::time::sleep(retry_after).await;
+				}
+				_ => break (status, utf),
+			}
+		};
 
 		// Log an error if the request was not sucessful (including the body as discord sends error information)
 		if !status.is_success() {
 			error!(
 				"Unsucsessful request. received response {} with body {}\n\nSending {} to {} with body:\n{}",
-				status, utf, method, uri, body
+				status, utf, method, uri, description
 			);
+			return Err(NetError::from_response(status, utf));
 		}
 
 		Ok(utf)
@@ -78,6 +348,157 @@ impl DiscordClient {
 
 	/// Connects the client to the gateway with the current token
 	pub async fn connect_gateway(&self, address: String) -> Option<websocket_handle::Connection> {
-		websocket_handle::connect_gateway(address, format!("Bot {}", self.token)).await
+		websocket_handle::connect_gateway(address, format!("Bot {}", self.token), self.compress).await
+	}
+}
+
+/// A header sent with every request from the client until this is dropped (derefs to the client so it can be used in its place)
+pub struct ScopedHeader<'a> {
+	client: &'a mut DiscordClient,
+	name: HeaderName,
+	/// The value from an outer scope, restored when this is dropped
+	previous: Option<HeaderValue>,
+}
+
+impl std::ops::Deref for ScopedHeader<'_> {
+	type Target = DiscordClient;
+	fn deref(&self) -> &Self::Target {
+		self.client
+	}
+}
+
+impl std::ops::DerefMut for ScopedHeader<'_> {
+	fn deref_mut(&mut self) -> &mut Self::Target {
+		self.client
 	}
 }
+
+impl Drop for ScopedHeader<'_> {
+	fn drop(&mut self) {
+		match self.previous.take() {
+			Some(previous) => self.client.headers.insert(self.name.clone(), previous),
+			None => self.client.headers.remove(&self.name),
+		};
+	}
+}
+
+#[test]
+fn multipart() {
+	let (content_type, body) = multipart_body(r#"{"content":"hi"}"#, &[FileUpload::new("a\"b.csv", "text/csv", "x,y\n1,2")]);
+	let boundary = content_type.strip_prefix("multipart/form-data; boundary=").unwrap();
+	let body = String::from_utf8(body).unwrap();
+	assert!(body.starts_with(&format!("--{boundary}\r\nContent-Disposition: form-data; name=\"payload_json\"\r\n")));
+	assert!(body.contains("{\"content\":\"hi\"}\r\n"));
+	assert!(body.contains("name=\"files[0]\"; filename=\"a_b.csv\"\r\nContent-Type: text/csv\r\n\r\nx,y\n1,2\r\n"));
+	assert!(body.ends_with(&format!("--{boundary}--\r\n")));
+}
+
+#[test]
+fn url_encoding() {
+	assert_eq!(DiscordClient::url_encode(1018447658685321266_u64), "1018447658685321266");
+	assert_eq!(DiscordClient::url_encode("a b/c?d&e=f"), "a%20b%2Fc%3Fd%26e%3Df");
+	assert_eq!(DiscordClient::url_encode("aW50-ZX_Jh.Y~3"), "aW50-ZX_Jh.Y~3");
+}
+
+#[test]
+fn audit_log_reason() {
+	let mut client = DiscordClient::new("token");
+	{
+		let mut client = client.audit_log_reason("/role assign by Käse");
+		assert_eq!(client.headers["x-audit-log-reason"], "%2Frole%20assign%20by%20K%C3%A4se");
+		{
+			let client = client.audit_log_reason("nested");
+			assert_eq!(client.headers["x-audit-log-reason"], "nested");
+		}
+		assert_eq!(client.headers["x-audit-log-reason"], "%2Frole%20assign%20by%20K%C3%A4se");
+	}
+	assert!(client.headers.is_empty());
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn audit_log_reason_on_member_and_channel_changes() {
+	use hyper::StatusCode;
+	use serde_json::json;
+	tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.unwrap()
+		.block_on(async {
+			let mock = crate::mock::MockDiscord::start().await;
+			let member = json!({"user": {"id": "2", "username": "Brie", "discriminator": "0"}, "nick": "Camembert", "roles": []});
+			mock.respond(Method::PATCH, "/guilds/1/members/2", StatusCode::OK, member);
+			mock.respond(
+				Method::PATCH,
+				"/channels/3",
+				StatusCode::OK,
+				json!({"id": "3", "type": 0, "name": "cheese"}),
+			);
+
+			let mut client = mock.client("token");
+			{
+				let client = &mut client.audit_log_reason("moderation");
+				crate::ModifyGuildMember::new()
+					.with_nick("Camembert")
+					.patch_modify_guild_member(client, 1, 2)
+					.await
+					.unwrap();
+				crate::ModifyChannel::new()
+					.with_name("cheese")
+					.patch_modify_channel(client, 3)
+					.await
+					.unwrap();
+			}
+			Channel::get_channel(&mut client, 3).await.unwrap_err();
+
+			let reasons = mock
+				.requests()
+				.iter()
+				.map(|request| request.headers.get("x-audit-log-reason").cloned())
+				.collect::<Vec<_>>();
+			assert_eq!(
+				reasons,
+				vec![
+					Some(HeaderValue::from_static("moderation")),
+					Some(HeaderValue::from_static("moderation")),
+					None
+				]
+			);
+		});
+}
+
+#[test]
+fn api_errors() {
+	let status = hyper::StatusCode::FORBIDDEN;
+	let error = NetError::from_response(status, r#"{"message": "Cannot send messages to this user", "code": 50007}"#.to_string());
+	assert!(error.is_dm_closed() && !error.is_retryable());
+
+	let body = r#"{"message": "Invalid Form Body", "code": 50035, "errors": {"content": {"_errors": [{"code": "BASE_TYPE_MAX_LENGTH"}]}}}"#;
+	let NetError::Api { code, message, errors, .. } = NetError::from_response(hyper::StatusCode::BAD_REQUEST, body.to_string()) else {
+		panic!("Expected an api error");
+	};
+	assert_eq!((code, message.as_str()), (Some(50035), "Invalid Form Body"));
+	assert_eq!(errors.unwrap()["content"]["_errors"][0]["code"], "BASE_TYPE_MAX_LENGTH");
+
+	// Proxies respond with html rather than json
+	let error = NetError::from_response(hyper::StatusCode::BAD_GATEWAY, "<html>502 Bad Gateway</html>".to_string());
+	assert!(matches!(&error, NetError::Api { code: None, message, .. } if message == "<html>502 Bad Gateway</html>"));
+	assert!(error.is_retryable());
+	assert!(NetError::from_response(hyper::StatusCode::INTERNAL_SERVER_ERROR, String::new()).is_retryable());
+	assert!(!NetError::from_response(hyper::StatusCode::NOT_FOUND, String::new()).is_retryable());
+}
+
+#[test]
+fn connection_errors_are_retryable() {
+	tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.unwrap()
+		.block_on(async {
+			// Nothing listens on the port so the request is never sent
+			let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+			let mut client = DiscordClient::new("token").with_api(format!("http://127.0.0.1:{port}"));
+			let error = crate::ChannelMessage::new().post_create(&mut client, 1).await.unwrap_err();
+			assert!(matches!(error, NetError::Hyper(_)) && error.is_retryable());
+		});
+}
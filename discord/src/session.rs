@@ -0,0 +1,161 @@
+use rand::Rng;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::{Dispatch, GatewayRecieve, GatewaySend, Identify, Resume};
+
+/// The state needed to resume a gateway session after the connection drops
+///
+/// Feed every received gateway message into [`GatewaySession::observe`] and use [`GatewaySession::handshake`] to reply to `Hello`.
+#[derive(Debug, Default, Clone)]
+pub struct GatewaySession {
+	/// The id of the session from the last `Ready`
+	pub session_id: Option<String>,
+	/// The url discord wants us to use when resuming
+	pub resume_gateway_url: Option<String>,
+	/// The last sequence number received (sent with heartbeats and resumes)
+	pub sequence_number: Option<usize>,
+	/// When the last heartbeat on this connection was sent
+	pub heartbeat_sent: Option<Instant>,
+	/// If discord has acknowledged the last heartbeat that was sent
+	pub heartbeat_acknowledged: bool,
+	/// The time between sending the last heartbeat and receiving the acknowledgement
+	pub latency: Option<Duration>,
+}
+
+impl GatewaySession {
+	/// Updates the session from a message received on the gateway
+	pub fn observe(&mut self, message: &GatewayRecieve) {
+		match message {
+			GatewayRecieve::Dispatch { s, d } => {
+				self.sequence_number = Some(*s);
+				if let Dispatch::Ready(ready) = d {
+					self.session_id = Some(ready.session_id.clone());
+					self.resume_gateway_url = Some(ready.resume_gateway_url.clone());
+				}
+			}
+			GatewayRecieve::InvalidSession { d: false } => self.invalidate(),
+			GatewayRecieve::Hello { .. } => {
+				// A new connection has no outstanding heartbeats
+				self.heartbeat_sent = None;
+				self.heartbeat_acknowledged = true;
+			}
+			GatewayRecieve::HeartbeatACK => {
+				self.heartbeat_acknowledged = true;
+				self.latency = self.heartbeat_sent.map(|sent| sent.elapsed());
+				debug!("Heartbeat acknowledged with latency {:?}", self.latency);
+			}
+			_ => {}
+		}
+	}
+
+	/// Handles the websocket closing, some close codes mean the session can not be resumed
+	///
+	/// https://discord.com/developers/docs/topics/opcodes-and-status-codes#gateway-gateway-close-event-codes
+	pub fn closed(&mut self, code: Option<u16>) {
+		if let Some(code @ (4003 | 4004 | 4007 | 4009 | 4010..=4014)) = code {
+			warn!("Gateway closed with code {code}, the session can not be resumed");
+			self.invalidate();
+		}
+	}
+
+	/// Forgets the session so the next connection identifies from scratch
+	pub fn invalidate(&mut self) {
+		self.session_id = None;
+		self.resume_gateway_url = None;
+		self.sequence_number = None;
+	}
+
+	/// Creates a heartbeat to send, recording when it was sent so the acknowledgement can be timed
+	pub fn heartbeat(&mut self) -> GatewaySend {
+		self.heartbeat_sent = Some(Instant::now());
+		self.heartbeat_acknowledged = false;
+		GatewaySend::Heartbeat { d: self.sequence_number }
+	}
+
+	/// Checks if a heartbeat was sent without discord acknowledging it, meaning the connection is dead and should be reconnected
+	///
+	/// https://discord.com/developers/docs/topics/gateway#sending-heartbeats
+	pub fn is_zombied(&self) -> bool {
+		self.heartbeat_sent.is_some() && !self.heartbeat_acknowledged
+	}
+
+	/// The delay before the first heartbeat, which discord asks to be a random fraction of the heartbeat interval
+	pub fn first_heartbeat_delay(heartbeat_interval: u64) -> Duration {
+		Duration::from_millis(heartbeat_interval).mul_f64(rand::random::<f64>())
+	}
+
+	/// The delay before reconnecting after an invalid session, which discord asks to be a random 1-5 seconds
+	pub fn invalid_session_delay() -> Duration {
+		Duration::from_millis(rand::thread_rng().gen_range(1000..=5000))
+	}
+
+	/// Checks if there is a session that can be resumed
+	pub fn can_resume(&self) -> bool {
+		self.session_id.is_some() && self.sequence_number.is_some()
+	}
+
+	/// The address to reconnect to if there is a session that can be resumed
+	pub fn gateway_url(&self) -> Option<&String> {
+		self.resume_gateway_url.as_ref().filter(|_| self.can_resume())
+	}
+
+	/// The payload to send in response to `Hello` - a resume if possible, otherwise the specified identify
+	pub fn handshake(&self, identify: Identify) -> GatewaySend {
+		match (&self.session_id, self.sequence_number) {
+			(Some(session_id), Some(seq)) => {
+				info!("Resuming session {session_id} from sequence {seq}");
+				GatewaySend::Resume {
+					d: Resume::new().with_token(identify.token).with_session_id(session_id).with_seq(seq),
+				}
+			}
+			_ => {
+				info!("Identifying a new session");
+				GatewaySend::Identify { d: identify }
+			}
+		}
+	}
+}
+
+#[test]
+fn resume_after_ready() {
+	let mut session = GatewaySession::default();
+	let identify = Identify::new().with_token("token");
+	assert!(matches!(session.handshake(identify.clone()), GatewaySend::Identify { .. }));
+
+	let ready = serde_json::from_str(
+		r#"{"t":"READY","s":1,"op":0,"d":{"v":10,"session_id":"abc","resume_gateway_url":"wss://resume.discord.gg","application":{"id":"1"}}}"#,
+	)
+	.unwrap();
+	session.observe(&ready);
+	session.observe(&GatewayRecieve::Dispatch {
+		s: 5,
+		d: Dispatch::Resumed(serde_json::Value::Null),
+	});
+	assert_eq!(session.gateway_url().map(String::as_str), Some("wss://resume.discord.gg"));
+	let GatewaySend::Resume { d } = session.handshake(identify.clone()) else {
+		panic!("Expected resume");
+	};
+	assert_eq!((d.session_id.as_str(), d.seq), ("abc", 5));
+
+	session.observe(&GatewayRecieve::InvalidSession { d: false });
+	assert!(matches!(session.handshake(identify), GatewaySend::Identify { .. }));
+}
+
+#[test]
+fn missed_heartbeat_ack() {
+	let mut session = GatewaySession::default();
+	session.observe(&GatewayRecieve::Hello {
+		d: crate::Hello::new().with_heartbeat_interval(41250_u64),
+	});
+	assert!(!session.is_zombied());
+
+	session.heartbeat();
+	assert!(session.is_zombied());
+	session.observe(&GatewayRecieve::HeartbeatACK);
+	assert!(!session.is_zombied() && session.latency.is_some());
+
+	assert!(GatewaySession::first_heartbeat_delay(41250) <= Duration::from_millis(41250));
+	let delay = GatewaySession::invalid_session_delay();
+	assert!(Duration::from_secs(1) <= delay && delay <= Duration::from_secs(5));
+}
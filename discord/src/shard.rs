@@ -0,0 +1,446 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_channel::{Receiver, Sender};
+use futures_util::StreamExt;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::websocket_handle::{self, Connection, Read};
+use crate::{DiscordClient, Dispatch, GatewayMeta, GatewayRecieve, GatewaySend, GatewaySession, Identify, NetError, PresenceUpdate, StartLimit};
+
+/// A dispatch received by one of the shards
+#[derive(Clone, Debug)]
+pub struct ShardEvent {
+	pub shard_id: u64,
+	pub dispatch: Dispatch,
+}
+
+/// Runs the number of gateway connections that discord recommends, merging their dispatches into one stream
+///
+/// Each shard heartbeats, resumes and reconnects on its own. The shards stop once the manager is dropped, or on close codes
+/// that reconnecting will not fix (such as an invalid token or intents that are not enabled in the developer portal).
+///
+/// https://discord.com/developers/docs/topics/gateway#sharding
+pub struct ShardManager {
+	pub shard_count: u64,
+	events: Receiver<ShardEvent>,
+	/// The tasks running each shard, which are aborted when the manager is dropped
+	tasks: Vec<JoinHandle<()>>,
+	shards: Vec<Arc<Mutex<ShardStatus>>>,
+	/// The presence sent when identifying, kept so that it survives reconnecting
+	presence: Arc<Mutex<Option<PresenceUpdate>>>,
+}
+
+/// The parts of a shard's connection that the manager can see
+#[derive(Debug, Default)]
+struct ShardStatus {
+	/// Sends payloads on the current connection, once the session is ready
+	outgoing: Option<Sender<String>>,
+	latency: Option<Duration>,
+}
+
+impl ShardManager {
+	/// Starts the recommended number of shards from `/gateway/bot`, identifying with the specified identify (with the shard filled in)
+	pub async fn start(client: &mut DiscordClient, identify: Identify) -> Result<Self, NetError> {
+		let meta = GatewayMeta::get_gateway_meta(client).await?;
+		info!("Starting {} shards with {:?}", meta.shards, meta.session_start_limit);
+
+		let shard_count = meta.shards.max(1);
+		let url = client.gateway.clone().unwrap_or(meta.url);
+		let limiter = Arc::new(Mutex::new(IdentifyLimiter::new(&meta.session_start_limit)));
+		let (send_events, events) = async_channel::unbounded();
+		let presence = Arc::new(Mutex::new(identify.presence.clone()));
+		let (mut tasks, mut shards) = (Vec::new(), Vec::new());
+		for id in 0..shard_count {
+			let status = Arc::new(Mutex::new(ShardStatus::default()));
+			let shard = Shard {
+				id,
+				url: url.clone(),
+				authorization: format!("Bot {}", client.token),
+				compress: client.compress,
+				identify: identify.clone().with_shard([id, shard_count]),
+				limiter: limiter.clone(),
+				events: send_events.clone(),
+				status: status.clone(),
+				presence: presence.clone(),
+			};
+			tasks.push(tokio::spawn(shard.run()));
+			shards.push(status);
+		}
+
+		Ok(Self {
+			shard_count,
+			events,
+			tasks,
+			shards,
+			presence,
+		})
+	}
+
+	/// Waits for the next dispatch from any of the shards, returning `None` once every shard has stopped
+	pub async fn next(&self) -> Option<ShardEvent> {
+		self.events.recv().await.ok()
+	}
+
+	/// The shard that receives the events for a guild
+	pub fn shard_for_guild(&self, guild_id: u64) -> u64 {
+		(guild_id >> 22) % self.shard_count
+	}
+
+	/// Updates the presence on every shard, which is also used when a shard has to identify again
+	///
+	/// https://discord.com/developers/docs/topics/gateway-events#update-presence
+	pub async fn update_presence(&self, presence: PresenceUpdate) {
+		*self.presence.lock().unwrap() = Some(presence.clone());
+		let payload = serde_json::to_string(&GatewaySend::PresenceUpdate { d: presence }).unwrap();
+		for (shard_id, status) in self.shards.iter().enumerate() {
+			let outgoing = status.lock().unwrap().outgoing.clone();
+			let Some(outgoing) = outgoing else {
+				debug!("Shard {shard_id} is not connected, its presence will be sent when it identifies");
+				continue;
+			};
+			if outgoing.send(payload.clone()).await.is_err() {
+				warn!("Failed to update the presence on shard {shard_id}, the gateway is closed");
+			}
+		}
+	}
+
+	/// The time between the last heartbeat on the shard and discord acknowledging it
+	pub fn latency(&self, shard_id: u64) -> Option<Duration> {
+		self.shards.get(shard_id as usize)?.lock().unwrap().latency
+	}
+}
+
+impl Drop for ShardManager {
+	fn drop(&mut self) {
+		for task in &self.tasks {
+			task.abort();
+		}
+	}
+}
+
+/// Limits how quickly sessions are identified, shared between all of the shards
+///
+/// https://discord.com/developers/docs/topics/gateway#session-start-limit-object
+#[derive(Debug)]
+struct IdentifyLimiter {
+	/// When the next identify can be sent in each rate limit bucket (`shard_id % max_concurrency`)
+	buckets: Vec<Instant>,
+	remaining: u64,
+	total: u64,
+	reset_at: Instant,
+}
+
+impl IdentifyLimiter {
+	/// Only one shard in each bucket can identify every 5 seconds
+	const INTERVAL: Duration = Duration::from_secs(5);
+	/// The start limit resets every day
+	const RESET_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+	fn new(limit: &StartLimit) -> Self {
+		let now = Instant::now();
+		Self {
+			buckets: vec![now; limit.max_concurrency.max(1) as usize],
+			remaining: limit.remaining,
+			total: limit.total,
+			reset_at: now + Duration::from_millis(limit.reset_after),
+		}
+	}
+
+	/// Reserves an identify for the shard, returning when it can be sent
+	fn reserve(&mut self, shard_id: u64) -> Instant {
+		let bucket = (shard_id % self.buckets.len() as u64) as usize;
+		let mut at = self.buckets[bucket].max(Instant::now());
+		if self.remaining == 0 {
+			warn!(
+				"Used all session starts, waiting {:?} for the limit to reset",
+				self.reset_at.saturating_duration_since(at)
+			);
+			at = at.max(self.reset_at);
+			self.remaining = self.total;
+			self.reset_at = at + Self::RESET_INTERVAL;
+		}
+		self.remaining = self.remaining.saturating_sub(1);
+		self.buckets[bucket] = at + Self::INTERVAL;
+		at
+	}
+
+	/// Gives back an identify reserved for `at` that was never sent, such as when connecting fails
+	fn refund(&mut self, shard_id: u64, at: Instant) {
+		let bucket = (shard_id % self.buckets.len() as u64) as usize;
+		// Only free the slot if no other shard has queued up behind it
+		if self.buckets[bucket] == at + Self::INTERVAL {
+			self.buckets[bucket] = at;
+		}
+		self.remaining = (self.remaining + 1).min(self.total);
+	}
+}
+
+#[test]
+fn identify_limiter() {
+	let limit = StartLimit {
+		total: 1000,
+		remaining: 3,
+		reset_after: 60_000,
+		max_concurrency: 2,
+	};
+	let start = Instant::now();
+	let mut limiter = IdentifyLimiter::new(&limit);
+
+	// Shards in different buckets can identify at the same time, but the same bucket must wait
+	assert!(limiter.reserve(0) < start + Duration::from_secs(1));
+	assert!(limiter.reserve(1) < start + Duration::from_secs(1));
+	assert!(limiter.reserve(2) >= start + IdentifyLimiter::INTERVAL);
+
+	// The start limit has been used up so the next identify waits for the reset
+	assert!(limiter.reserve(3) >= start + Duration::from_secs(60));
+	assert_eq!(limiter.remaining, 999);
+
+	// A refunded identify can be used again straight away
+	let at = limiter.reserve(4);
+	limiter.refund(4, at);
+	assert_eq!(limiter.remaining, 999);
+	assert_eq!(limiter.reserve(4), at);
+}
+
+/// A message for the task running a shard's connection
+#[allow(clippy::large_enum_variant)]
+enum ShardMessage {
+	Gateway(GatewayRecieve),
+	Closed(Option<u16>),
+	Heartbeat,
+}
+
+/// A single gateway connection which is reconnected until the manager is dropped
+struct Shard {
+	id: u64,
+	url: String,
+	authorization: String,
+	compress: bool,
+	identify: Identify,
+	limiter: Arc<Mutex<IdentifyLimiter>>,
+	events: Sender<ShardEvent>,
+	status: Arc<Mutex<ShardStatus>>,
+	presence: Arc<Mutex<Option<PresenceUpdate>>>,
+}
+
+impl Shard {
+	/// How long to wait before trying again if connecting fails
+	const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+	async fn run(self) {
+		let mut session = GatewaySession::default();
+		loop {
+			let reconnect = self.connection(&mut session).await;
+			// Dropping the last sender closes the websocket
+			self.status.lock().unwrap().outgoing = None;
+			if !reconnect {
+				return;
+			}
+			info!("Reconnecting shard {}", self.id);
+		}
+	}
+
+	/// Runs one connection to the gateway, returning if the shard should reconnect
+	async fn connection(&self, session: &mut GatewaySession) -> bool {
+		// Reserve the identify before connecting, as discord closes connections that take too long to identify
+		let reserved = (!session.can_resume()).then(|| self.limiter.lock().unwrap().reserve(self.id));
+		if let Some(at) = reserved {
+			tokio::time::sleep_until(at).await;
+		}
+		let address = session.gateway_url().unwrap_or(&self.url).clone();
+		let Some(Connection { send_outgoing_message, read }) =
+			websocket_handle::connect_gateway(address, self.authorization.clone(), self.compress).await
+		else {
+			// Nothing was sent, so the identify should not count towards the start limit
+			if let Some(at) = reserved {
+				self.limiter.lock().unwrap().refund(self.id, at);
+			}
+			tokio::time::sleep(Self::RECONNECT_DELAY).await;
+			return !self.events.is_closed();
+		};
+
+		let (send, receive) = async_channel::unbounded();
+		tokio::spawn(read_gateway(read, send.clone()));
+
+		while let Ok(message) = receive.recv().await {
+			let reply = match message {
+				ShardMessage::Gateway(message) => {
+					session.observe(&message);
+					match message {
+						GatewayRecieve::Dispatch { d, .. } => {
+							// Presence updates can only be sent once the session is ready
+							if matches!(d, Dispatch::Ready(_) | Dispatch::Resumed(_)) {
+								self.status.lock().unwrap().outgoing = Some(send_outgoing_message.clone());
+							}
+							let event = ShardEvent {
+								shard_id: self.id,
+								dispatch: d,
+							};
+							if self.events.send(event).await.is_err() {
+								return false;
+							}
+							None
+						}
+						GatewayRecieve::Heartbeat { .. } => Some(session.heartbeat()),
+						GatewayRecieve::Reconnect => {
+							info!("Discord has told shard {} to reconnect", self.id);
+							return true;
+						}
+						GatewayRecieve::InvalidSession { d } => {
+							warn!("Invalid session on shard {}, can resume {}", self.id, d);
+							tokio::time::sleep(GatewaySession::invalid_session_delay()).await;
+							return true;
+						}
+						GatewayRecieve::Hello { d } => {
+							let delay = GatewaySession::first_heartbeat_delay(d.heartbeat_interval);
+							tokio::spawn(heartbeat(send.clone(), delay, Duration::from_millis(d.heartbeat_interval)));
+							let mut identify = self.identify.clone();
+							identify.presence = self.presence.lock().unwrap().clone();
+							Some(session.handshake(identify))
+						}
+						GatewayRecieve::HeartbeatACK => {
+							self.status.lock().unwrap().latency = session.latency;
+							None
+						}
+					}
+				}
+				ShardMessage::Closed(code) => {
+					session.closed(code);
+					// Authentication, sharding, version and intent errors will not be fixed by reconnecting
+					if let Some(code @ (4004 | 4010..=4014)) = code {
+						error!("Shard {} closed with code {code}, stopping", self.id);
+						return false;
+					}
+					return true;
+				}
+				ShardMessage::Heartbeat => {
+					if session.is_zombied() {
+						warn!("Discord did not acknowledge the last heartbeat on shard {}, reconnecting", self.id);
+						return true;
+					}
+					Some(session.heartbeat())
+				}
+			};
+			if let Some(reply) = reply {
+				if send_outgoing_message.send(serde_json::to_string(&reply).unwrap()).await.is_err() {
+					return true;
+				}
+			}
+		}
+		true
+	}
+}
+
+/// Forwards the messages from the gateway until it closes
+async fn read_gateway(mut read: Read, send: Sender<ShardMessage>) {
+	let mut close_code = None;
+	loop {
+		let text = match read.next().await {
+			Some(Ok(Message::Text(text))) => text,
+			Some(Ok(Message::Binary(data))) => String::from_utf8_lossy(&data).into_owned(),
+			Some(Ok(Message::Close(frame))) => {
+				close_code = frame.map(|frame| u16::from(frame.code));
+				break;
+			}
+			Some(Ok(_)) => continue,
+			_ => break,
+		};
+		match serde_json::from_str(&text) {
+			Ok(message) => {
+				if send.send(ShardMessage::Gateway(message)).await.is_err() {
+					return;
+				}
+			}
+			Err(e) => error!("Error decoding gateway message {:?}", e),
+		}
+	}
+	send.send(ShardMessage::Closed(close_code)).await.unwrap_or(())
+}
+
+/// Asks the connection to send a heartbeat every `interval` after `delay`, until the connection is closed
+async fn heartbeat(send: Sender<ShardMessage>, delay: Duration, interval: Duration) {
+	let mut interval = tokio::time::interval_at(Instant::now() + delay, interval);
+	loop {
+		interval.tick().await;
+		if send.send(ShardMessage::Heartbeat).await.is_err() {
+			return;
+		}
+	}
+}
+
+#[cfg(feature = "mock")]
+#[test]
+fn shards_merge_dispatches() {
+	use crate::mock::MockDiscord;
+	use serde_json::json;
+	tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.unwrap()
+		.block_on(async {
+			let mock = MockDiscord::start().await;
+			let limit = json!({"total": 1000, "remaining": 1000, "reset_after": 0, "max_concurrency": 2});
+			let meta = json!({"url": mock.gateway, "shards": 2, "session_start_limit": limit});
+			mock.respond(hyper::Method::GET, "/gateway/bot", hyper::StatusCode::OK, meta);
+
+			let mut client = mock.client("token");
+			let manager = ShardManager::start(&mut client, Identify::new().with_token("token")).await.unwrap();
+			for _ in 0..2 {
+				assert!(matches!(manager.next().await.unwrap().dispatch, Dispatch::Ready(_)));
+			}
+			let mut shards = mock
+				.gateway_received()
+				.into_iter()
+				.filter(|payload| payload["op"] == 2)
+				.map(|payload| payload["d"]["shard"].clone())
+				.collect::<Vec<_>>();
+			shards.sort_by_key(|shard| shard[0].as_u64());
+			assert_eq!(shards, vec![json!([0, 2]), json!([1, 2])]);
+
+			// Both shards are ready, so the presence is sent on each of them
+			manager.update_presence(PresenceUpdate::new().with_status(crate::Status::Dnd)).await;
+			mock.wait_until(|mock| mock.gateway_received().iter().filter(|payload| payload["op"] == 3).count() == 2)
+				.await;
+
+			// The mock sends dispatches to the most recent connection, whichever shard that is
+			mock.dispatch("RESUMED", serde_json::Value::Null).await;
+			assert!(matches!(manager.next().await.unwrap().dispatch, Dispatch::Resumed(_)));
+		});
+}
+
+#[test]
+fn failed_connect_refunds_identify() {
+	let limit = StartLimit {
+		total: 1000,
+		remaining: 10,
+		reset_after: 60_000,
+		max_concurrency: 1,
+	};
+	let limiter = Arc::new(Mutex::new(IdentifyLimiter::new(&limit)));
+	let (events, _receive) = async_channel::unbounded();
+	let shard = Shard {
+		id: 0,
+		// Nothing listens on port 1, so connecting fails straight away
+		url: "ws://127.0.0.1:1".to_string(),
+		authorization: "Bot token".to_string(),
+		compress: false,
+		identify: Identify::new().with_token("token"),
+		limiter: limiter.clone(),
+		events,
+		status: Default::default(),
+		presence: Default::default(),
+	};
+	tokio::runtime::Builder::new_current_thread()
+		.enable_all()
+		.build()
+		.unwrap()
+		.block_on(async {
+			// The refund happens before waiting to reconnect, so there is no need to wait for the whole delay
+			let mut session = GatewaySession::default();
+			let _ = tokio::time::timeout(Duration::from_secs(2), shard.connection(&mut session)).await;
+		});
+	assert_eq!(limiter.lock().unwrap().remaining, 10);
+}
@@ -0,0 +1,302 @@
+//! Commands declared as rust types, so that the registered definition and the parsed options come from the same place
+//!
+//! Derive [`SlashCommand`] for each command and [`CommandList`] for an enum of all of them:
+//! - A struct is a command (or subcommand) with its fields as the options. The type of a field decides the option type
+//!   (see [`CommandOption`]) and `Option` fields are not required.
+//! - An enum is a command (or subcommand group) with its variants as the subcommands. Unit variants are subcommands without
+//!   options and tuple variants contain another [`SlashCommand`].
+//!
+//! Doc comments are used as the descriptions. The `#[command(...)]` attribute can set:
+//! - `name = "..."` on the type or a variant (defaults to the lowercase identifier) or on a field (defaults to the identifier)
+//! - `name_localizations = [("fr", "...")]` and `description_localizations = [...]` on the type, a variant or a field
+//! - `default_member_permissions = ...`, `contexts = [InteractionContextType::Guild]` and `nsfw = true` on a top level command
+//! - `autocomplete = ...` on a field to autocomplete it with a provider, which can be anything (such as a function pointer)
+//!   of the type set with `autocomplete = Type` on the struct. Look up the provider with [`CommandAutocomplete`].
+//! - `range = min..=max` on an integer or number field
+//! - `min_length = ...` and `max_length = ...` on a string field
+//! - `channel_types = [...]` on a channel field
+//! - `choices = [("name", value)]` on a string, integer or number field (see [`CommandChoice`])
+//!
+//! Discord only allows a command to have subcommand groups of subcommands, so enums can not be nested any deeper.
+
+use std::collections::HashMap;
+
+use crate::{
+	ApplicationCommand, ApplicationCommandList, ApplicationCommandOption, ApplicationCommandOptionChoice, Attachment, Channel, CommandOptionType,
+	CommandOptions, CommandType, GuildMember, InteractionContextType, Mentionable, OptionError, OptionType, Permissions, Role, User,
+};
+
+/// A command, or a subcommand of another command
+pub trait SlashCommand: Sized {
+	const NAME: &'static str;
+	const DESCRIPTION: &'static str;
+	/// The type when used inside another command (a subcommand for structs and a subcommand group for enums)
+	const OPTION_TYPE: CommandOptionType;
+	const DEFAULT_MEMBER_PERMISSIONS: Option<Permissions> = None;
+	/// Where a top level command can be used (everywhere if `None`)
+	const CONTEXTS: Option<&'static [InteractionContextType]> = None;
+	/// If a top level command is age restricted
+	const NSFW: bool = false;
+	/// How many levels of subcommands there are below this (0 for a command with options)
+	const DEPTH: u8 = 0;
+
+	/// The options, or the subcommands for an enum
+	fn options() -> Vec<ApplicationCommandOption>;
+
+	/// Reads the command from the names of the subcommands used (`["create"]` for `/bill create`) and the options
+	fn parse(subcommands: &[&str], options: &CommandOptions) -> Result<Self, OptionError>;
+
+	/// The name of a top level command in other languages, by locale
+	fn name_localizations() -> Option<HashMap<String, String>> {
+		None
+	}
+
+	/// The description of a top level command in other languages, by locale
+	fn description_localizations() -> Option<HashMap<String, String>> {
+		None
+	}
+
+	/// The definition to register as a top level command
+	fn command() -> ApplicationCommand {
+		let command = ApplicationCommand::new()
+			.with_command_type(CommandType::Chat)
+			.with_name(Self::NAME)
+			.with_description(Self::DESCRIPTION);
+		ApplicationCommand {
+			options: Some(Self::options()).filter(|options| !options.is_empty()),
+			default_member_permissions: Self::DEFAULT_MEMBER_PERMISSIONS,
+			contexts: Self::CONTEXTS.map(<[_]>::to_vec),
+			nsfw: Self::NSFW.then_some(true),
+			name_localizations: Self::name_localizations(),
+			description_localizations: Self::description_localizations(),
+			..command
+		}
+	}
+
+	/// The definition as a subcommand (or subcommand group) of another command
+	fn subcommand(name: &str, description: &str) -> ApplicationCommandOption {
+		let option = ApplicationCommandOption::new()
+			.with_option_type(Self::OPTION_TYPE)
+			.with_name(name)
+			.with_description(description);
+		ApplicationCommandOption {
+			options: Some(Self::options()).filter(|options| !options.is_empty()),
+			..option
+		}
+	}
+}
+
+/// Finds the autocomplete provider of type `A` for an option
+///
+/// Derived along with [`SlashCommand`], commands without autocompleted options implement this for every `A`.
+pub trait CommandAutocomplete<A> {
+	fn autocomplete(subcommands: &[&str], option: &str) -> Option<A>;
+}
+
+/// All of the commands of an application, derived for an enum with a variant for each [`SlashCommand`]
+pub trait CommandList: Sized {
+	/// The definitions to register
+	fn commands() -> ApplicationCommandList;
+
+	/// Reads the command from its name including the subcommands (`["bill", "create"]` for `/bill create`) and the options
+	fn parse(command: &[&str], options: &CommandOptions) -> Result<Self, OptionError>;
+}
+
+/// A type that can be read from the value of an option
+pub trait CommandOption: Sized {
+	const OPTION_TYPE: CommandOptionType;
+	const REQUIRED: bool = true;
+
+	fn read(options: &CommandOptions, name: &str) -> Result<Self, OptionError>;
+}
+
+macro_rules! command_option {
+	($($ty:ty => $option_type:ident $read:ident,)*) => {
+		$(
+			impl CommandOption for $ty {
+				const OPTION_TYPE: CommandOptionType = CommandOptionType::$option_type;
+
+				fn read(options: &CommandOptions, name: &str) -> Result<Self, OptionError> {
+					options.$read(name)
+				}
+			}
+		)*
+	};
+}
+
+command_option! {
+	String => String string,
+	i64 => Integer integer,
+	f64 => Number number,
+	bool => Bool bool,
+	User => User user,
+	GuildMember => User member,
+	Role => Role role,
+	Channel => Channel channel,
+	Attachment => Attachment attachment,
+	Mentionable => Mentionable mentionable,
+}
+
+/// A type of option that can be limited to a list of choices, with `#[command(choices = [("name", value)])]`
+pub trait CommandChoice {
+	type Value;
+
+	fn choice(name: &str, value: impl Into<Self::Value>) -> ApplicationCommandOptionChoice;
+}
+
+macro_rules! command_choice {
+	($($ty:ty => $option_type:ident,)*) => {
+		$(
+			impl CommandChoice for $ty {
+				type Value = $ty;
+
+				fn choice(name: &str, value: impl Into<Self::Value>) -> ApplicationCommandOptionChoice {
+					ApplicationCommandOptionChoice::new().with_name(name).with_value(OptionType::$option_type(value.into()))
+				}
+			}
+		)*
+	};
+}
+
+command_choice! {
+	String => String,
+	i64 => Integer,
+	f64 => Number,
+}
+
+impl<T: CommandChoice> CommandChoice for Option<T> {
+	type Value = T::Value;
+
+	fn choice(name: &str, value: impl Into<Self::Value>) -> ApplicationCommandOptionChoice {
+		T::choice(name, value)
+	}
+}
+
+/// Collects `(locale, text)` pairs for the `name_localizations` and `description_localizations` arguments
+pub fn localizations<'a>(localizations: impl IntoIterator<Item = (&'a str, &'a str)>) -> HashMap<String, String> {
+	localizations
+		.into_iter()
+		.map(|(locale, text)| (locale.to_string(), text.to_string()))
+		.collect()
+}
+
+impl<T: CommandOption> CommandOption for Option<T> {
+	const OPTION_TYPE: CommandOptionType = T::OPTION_TYPE;
+	const REQUIRED: bool = false;
+
+	fn read(options: &CommandOptions, name: &str) -> Result<Self, OptionError> {
+		match T::read(options, name) {
+			Err(OptionError::Missing(_)) => Ok(None),
+			value => value.map(Some),
+		}
+	}
+}
+
+#[test]
+fn derive_slash_command() {
+	type Provider = fn() -> Vec<&'static str>;
+
+	/// Cheese commands
+	#[derive(SlashCommand, Debug, PartialEq)]
+	#[command(default_member_permissions = Permissions::MANAGE_GUILD)]
+	enum Cheese {
+		/// Eat some cheese
+		Eat(EatCheese),
+		/// Throw the cheese away
+		#[command(name = "bin", name_localizations = [("fr", "jeter")])]
+		Throw,
+	}
+
+	/// Eat
+	#[derive(SlashCommand, Debug, PartialEq)]
+	#[command(autocomplete = Provider)]
+	struct EatCheese {
+		/// The kind of cheese
+		#[command(autocomplete = || vec!["brie", "cheddar"])]
+		kind: String,
+		/// How much to eat
+		#[command(name = "grams", range = 0. ..=100.)]
+		amount: Option<f64>,
+	}
+
+	/// About the bot
+	#[derive(SlashCommand, Debug, PartialEq)]
+	struct About;
+
+	/// Label the cheese
+	#[derive(SlashCommand, Debug)]
+	#[command(contexts = [InteractionContextType::Guild], nsfw = true, name_localizations = [("fr", "etiquette")])]
+	struct Label {
+		/// The text on the label
+		#[command(min_length = 1, max_length = 20, description_localizations = [("fr", "Le texte")])]
+		text: String,
+		/// How many labels
+		#[command(range = 1..=10, choices = [("one", 1), ("ten", 10)])]
+		count: Option<i64>,
+		/// Where to put the label
+		#[command(channel_types = [0, 5])]
+		channel: Option<Channel>,
+	}
+
+	#[derive(CommandList, Debug, PartialEq)]
+	enum Command {
+		Cheese(Cheese),
+		About(About),
+	}
+
+	let commands = Command::commands().commands;
+	assert_eq!(commands[1], About::command());
+	assert_eq!(commands[0].default_member_permissions, Some(Permissions::MANAGE_GUILD));
+	let subcommands = commands[0].options.clone().unwrap();
+	assert_eq!((subcommands[1].name.as_str(), subcommands[1].options.is_none()), ("bin", true));
+	assert_eq!(subcommands[1].name_localizations.as_ref().unwrap()["fr"], "jeter");
+	let eat = &subcommands[0];
+	assert_eq!(
+		(eat.option_type.clone(), eat.description.as_str()),
+		(CommandOptionType::SubCommand, "Eat some cheese")
+	);
+	let amount = &eat.options.as_ref().unwrap()[1];
+	assert_eq!((amount.name.as_str(), amount.required, amount.max_value), ("grams", false, Some(100.)));
+
+	let options = [("kind".to_string(), crate::OptionType::String("brie".to_string()))]
+		.into_iter()
+		.collect();
+	assert_eq!(
+		Command::parse(&["cheese", "eat"], &options),
+		Ok(Command::Cheese(Cheese::Eat(EatCheese {
+			kind: "brie".to_string(),
+			amount: None
+		})))
+	);
+	assert_eq!(Command::parse(&["cheese", "bin"], &options), Ok(Command::Cheese(Cheese::Throw)));
+	// Commands with options or without subcommands have nothing below them
+	for command in [&["cheese", "eat", "quickly"][..], &["cheese", "bin", "now"], &["about", "cheese"]] {
+		assert_eq!(Command::parse(command, &options), Err(OptionError::UnknownCommand(command.join(" "))));
+	}
+	assert_eq!(
+		Command::parse(&["cheese", "melt"], &options),
+		Err(OptionError::UnknownCommand("cheese melt".to_string()))
+	);
+
+	let label = Label::command();
+	assert_eq!((label.contexts, label.nsfw), (Some(vec![InteractionContextType::Guild]), Some(true)));
+	assert_eq!(label.name_localizations.unwrap()["fr"], "etiquette");
+	let options = label.options.unwrap();
+	assert_eq!((options[0].min_length, options[0].max_length), (Some(1), Some(20)));
+	assert_eq!(options[0].description_localizations.as_ref().unwrap()["fr"], "Le texte");
+	assert_eq!((options[1].min_value, options[1].max_value), (Some(1.), Some(10.)));
+	assert_eq!(options[1].choices.as_ref().unwrap()[1].value, OptionType::Integer(10));
+	assert_eq!(options[2].channel_types, Some(vec![0, 5]));
+	let options = [
+		("text".to_string(), OptionType::String("aged".to_string())),
+		("count".to_string(), OptionType::Integer(10)),
+	]
+	.into_iter()
+	.collect();
+	let label = Label::parse(&[], &options).unwrap();
+	assert_eq!((label.text.as_str(), label.count, label.channel.is_none()), ("aged", Some(10), true));
+
+	let provider = <Command as CommandAutocomplete<Provider>>::autocomplete(&["cheese", "eat"], "kind").unwrap();
+	assert_eq!(provider(), vec!["brie", "cheddar"]);
+	assert!(<Command as CommandAutocomplete<Provider>>::autocomplete(&["cheese", "eat"], "grams").is_none());
+}
@@ -1,22 +1,143 @@
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
 use crate::websocket_handle::tungstenite::Message;
 use async_channel::{Receiver, Sender};
+use flate2::{Decompress, FlushDecompress};
 use futures_util::stream::{SplitSink, SplitStream};
-use futures_util::{SinkExt, StreamExt};
+use futures_util::{SinkExt, Stream, StreamExt};
 use hyper::header::{HeaderValue, AUTHORIZATION, USER_AGENT};
 use tokio::net::TcpStream;
 use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
 use tokio_tungstenite::*;
 
-pub type Read = SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>;
+/// The messages received from the gateway, with compressed binary frames inflated into text
+pub struct Read {
+	stream: SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>,
+	zlib: Option<ZlibStream>,
+}
+
+impl Stream for Read {
+	type Item = Result<Message, tungstenite::Error>;
+
+	fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+		let this = self.get_mut();
+		loop {
+			let message = ready!(this.stream.poll_next_unpin(cx));
+			let (Some(Ok(Message::Binary(data))), Some(zlib)) = (&message, &mut this.zlib) else {
+				return Poll::Ready(message);
+			};
+			// Payloads can be split across several frames, so wait for the rest
+			match zlib.push(data) {
+				Ok(Some(text)) => return Poll::Ready(Some(Ok(Message::Text(text)))),
+				Ok(None) => continue,
+				Err(e) => return Poll::Ready(Some(Err(tungstenite::Error::Io(e)))),
+			}
+		}
+	}
+}
+
+/// Inflates a `zlib-stream` compressed connection, which shares one zlib context for every payload
+///
+/// https://discord.com/developers/docs/topics/gateway#zlibstream
+pub struct ZlibStream {
+	inflate: Decompress,
+	buffer: Vec<u8>,
+}
+
+impl Default for ZlibStream {
+	fn default() -> Self {
+		Self {
+			inflate: Decompress::new(true),
+			buffer: Vec::new(),
+		}
+	}
+}
+
+impl ZlibStream {
+	/// The end of a `Z_SYNC_FLUSH`, which discord sends at the end of every payload
+	const SUFFIX: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+	/// Adds a binary frame, returning the payload once it is complete
+	pub fn push(&mut self, frame: &[u8]) -> std::io::Result<Option<String>> {
+		self.buffer.extend_from_slice(frame);
+		if !self.buffer.ends_with(&Self::SUFFIX) {
+			return Ok(None);
+		}
+
+		fn invalid(e: impl std::error::Error + Send + Sync + 'static) -> std::io::Error {
+			std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+		}
+		let mut output = Vec::with_capacity(self.buffer.len() * 4);
+		let mut consumed = 0;
+		loop {
+			let (total_in, produced) = (self.inflate.total_in(), output.len());
+			self.inflate
+				.decompress_vec(&self.buffer[consumed..], &mut output, FlushDecompress::Sync)
+				.map_err(invalid)?;
+			consumed += (self.inflate.total_in() - total_in) as usize;
+			// There is always space in the output here, so no progress means the stream is corrupt (or has ended)
+			if self.inflate.total_in() == total_in && output.len() == produced {
+				self.buffer.clear();
+				return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "zlib stream made no progress"));
+			}
+			// Inflating stops when the output is full, so grow it until everything is consumed
+			if consumed == self.buffer.len() && output.len() < output.capacity() {
+				break;
+			}
+			output.reserve(output.capacity().max(1024));
+		}
+		self.buffer.clear();
+
+		String::from_utf8(output).map(Some).map_err(invalid)
+	}
+}
+
+#[test]
+fn zlib_stream() {
+	use flate2::{Compress, Compression, FlushCompress};
+	let mut compress = Compress::new(Compression::default(), true);
+	let mut deflate = |payload: &str| {
+		let mut output = Vec::with_capacity(payload.len() + 64);
+		compress.compress_vec(payload.as_bytes(), &mut output, FlushCompress::Sync).unwrap();
+		output
+	};
+	let first = deflate(r#"{"op":10,"d":{"heartbeat_interval":41250}}"#);
+	let second = deflate(r#"{"op":11}"#);
+
+	let mut zlib = ZlibStream::default();
+	assert_eq!(
+		zlib.push(&first).unwrap().as_deref(),
+		Some(r#"{"op":10,"d":{"heartbeat_interval":41250}}"#)
+	);
+	// The second payload split across two frames (and relying on the context from the first)
+	let (start, end) = second.split_at(3);
+	assert_eq!(zlib.push(start).unwrap(), None);
+	assert_eq!(zlib.push(end).unwrap().as_deref(), Some(r#"{"op":11}"#));
+
+	// Once the stream has finished, inflating can not make progress so the data is rejected rather than looping forever
+	let mut finished = Compress::new(Compression::default(), true);
+	let mut output = Vec::with_capacity(64);
+	finished.compress_vec(b"{}", &mut output, FlushCompress::Finish).unwrap();
+	output.extend_from_slice(&ZlibStream::SUFFIX);
+	let mut zlib = ZlibStream::default();
+	assert_eq!(zlib.push(&output).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+}
 
 pub struct Connection {
 	pub send_outgoing_message: Sender<String>,
 	pub read: Read,
 }
 
-/// Connects to the gateway
-pub async fn connect_gateway(address: String, header: String) -> Option<Connection> {
-	let uri = address;
+/// Connects to the gateway, asking for `zlib-stream` compression if `compress` is set
+pub async fn connect_gateway(address: String, header: String, compress: bool) -> Option<Connection> {
+	let mut uri = address;
+	if compress {
+		uri.push(if uri.contains('?') { '&' } else { '?' });
+		uri.push_str("compress=zlib-stream");
+	}
 	info!("Connecting to {}", uri,);
 
 	// Add auth headers
@@ -37,7 +158,11 @@ pub async fn connect_gateway(address: String, header: String) -> Option<Connecti
 	};
 
 	// Split socket into reader and writer
-	let (write, read) = socket.split();
+	let (write, stream) = socket.split();
+	let read = Read {
+		stream,
+		zlib: compress.then(ZlibStream::default),
+	};
 
 	// Allow communications with outgoing message handlers
 	let (send_outgoing_message, handle_outgoing_message) = async_channel::unbounded();
@@ -48,12 +173,24 @@ pub async fn connect_gateway(address: String, header: String) -> Option<Connecti
 }
 
 /// Sends outgoing messages that are received from the async-channel
+///
+/// Once every sender is dropped the websocket is closed with a non 1000 code, so that the session can still be resumed.
 async fn outgoing_messages(
 	mut write: SplitSink<WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>, Message>,
 	handle_outgoing_message: Receiver<String>,
 ) {
 	while let Ok(x) = handle_outgoing_message.recv().await {
 		debug!("Sent message {}", x);
-		write.send(Message::Text(x)).await.unwrap();
+		if let Err(e) = write.send(Message::Text(x)).await {
+			error!("Failed to send gateway message {e:?}");
+			return;
+		}
+	}
+	let frame = CloseFrame {
+		code: CloseCode::Library(4000),
+		reason: "Reconnecting".into(),
+	};
+	if let Err(e) = write.send(Message::Close(Some(frame))).await {
+		debug!("Failed to close gateway {e:?}");
 	}
 }
@@ -3,9 +3,10 @@ extern crate proc_macro;
 mod builder_pattern;
 mod request_builder;
 mod serialise_tag;
+mod slash_command;
 
 use proc_macro::TokenStream;
-use syn::{parse::Nothing, parse_macro_input, ItemStruct, LitStr};
+use syn::{parse::Nothing, parse_macro_input, DeriveInput, ItemStruct, LitStr};
 
 /// Generates the builder pattern and derives serialize for struct
 #[proc_macro_attribute]
@@ -36,3 +37,19 @@ pub fn serialise_tag(args: TokenStream, input: TokenStream) -> TokenStream {
 
 	TokenStream::from(serialise_tag::serialize_tag(input, &arguments))
 }
+
+/// Derives `SlashCommand` and `CommandAutocomplete` for a command struct (with options) or enum (with subcommands)
+#[proc_macro_derive(SlashCommand, attributes(command))]
+pub fn slash_command(input: TokenStream) -> TokenStream {
+	let input = parse_macro_input!(input as DeriveInput);
+
+	TokenStream::from(slash_command::slash_command(input).unwrap_or_else(|e| e.to_compile_error()))
+}
+
+/// Derives `CommandList` and `CommandAutocomplete` for an enum of all of the commands
+#[proc_macro_derive(CommandList, attributes(command))]
+pub fn command_list(input: TokenStream) -> TokenStream {
+	let input = parse_macro_input!(input as DeriveInput);
+
+	TokenStream::from(slash_command::command_list(input).unwrap_or_else(|e| e.to_compile_error()))
+}
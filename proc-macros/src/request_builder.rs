@@ -22,6 +22,29 @@ pub struct RequestBuilderInput {
 
 	/// (if not a get request) the return type of the request
 	return_ty: Option<Type>,
+
+	/// If a variant which uploads files should also be generated (`upload`)
+	upload: bool,
+
+	/// Optional query parameters, declared after a `;` (e.g. `; limit: u8, after: String`)
+	query: Vec<QueryParameter>,
+}
+
+/// A query parameter which is only added to the url if it is `Some`
+pub struct QueryParameter {
+	name: Ident,
+	_colon: Token!(:),
+	ty: Type,
+}
+
+impl Parse for QueryParameter {
+	fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+		Ok(QueryParameter {
+			name: input.parse()?,
+			_colon: input.parse()?,
+			ty: input.parse()?,
+		})
+	}
 }
 
 impl Parse for RequestBuilderInput {
@@ -49,15 +72,38 @@ impl Parse for RequestBuilderInput {
 					None
 				}
 			},
+			upload: {
+				if input.peek(Ident) {
+					let upload: Ident = input.parse()?;
+					if upload != "upload" {
+						return Err(syn::Error::new(upload.span(), "expected `upload` or `=`"));
+					}
+					true
+				} else {
+					false
+				}
+			},
 			_eq: input.parse()?,
 			request_method: input.parse()?,
 
 			format: input.parse()?,
-			fields: if input.peek(Token!(as)) {
-				let _as: Token!(as) = input.parse()?;
+			fields: {
+				let mut fields = Vec::new();
+				if input.peek(Token!(as)) {
+					let _as: Token!(as) = input.parse()?;
+					fields.push(input.parse()?);
+					while input.peek(Token!(,)) {
+						let _comma: Token!(,) = input.parse()?;
+						fields.push(input.parse()?);
+					}
+				}
+				fields
+			},
+			query: if input.peek(Token!(;)) {
+				let _semi: Token!(;) = input.parse()?;
 
-				let fields = input.parse_terminated::<Ident, Token!(,)>(Ident::parse)?;
-				fields.into_iter().collect()
+				let query = input.parse_terminated::<QueryParameter, Token!(,)>(QueryParameter::parse)?;
+				query.into_iter().collect()
 			} else {
 				Vec::new()
 			},
@@ -71,9 +117,20 @@ impl RequestBuilderInput {
 		let format = self.format;
 
 		let decleration = quote! {pub async fn #fn_name<'a>};
+		let query_names = self.query.iter().map(|parameter| &parameter.name).collect::<Vec<_>>();
+		let query_types = self.query.iter().map(|parameter| &parameter.ty);
+		// The path and query parameters to the function
+		let parameters = quote! {#(#fields: impl std::fmt::Display,)* #(#query_names: Option<#query_types>,)*};
 		let endpoint = quote! {
-			let mut endpoint = DiscordClient::API.to_string();
-			endpoint.push_str(&format!(#format, #(#fields = #fields),*));
+			let mut endpoint = client.api.clone();
+			endpoint.push_str(&format!(#format, #(#fields = DiscordClient::url_encode(#fields)),*));
+			#(
+				if let Some(value) = &#query_names {
+					endpoint.push(if endpoint.contains('?') { '&' } else { '?' });
+					endpoint.push_str(concat!(stringify!(#query_names), "="));
+					endpoint.push_str(&DiscordClient::url_encode(value));
+				}
+			)*
 		};
 
 		let stream = match self.request_method.to_string().as_str() {
@@ -84,13 +141,23 @@ impl RequestBuilderInput {
 					quote!(Self)
 				};
 				quote! {
-					#decleration (client: &'a mut DiscordClient, #(#fields: impl std::fmt::Display),*) -> Result<#return_value, NetError>{
+					#decleration (client: &'a mut DiscordClient, #parameters) -> Result<#return_value, NetError>{
 						#endpoint
 						let response = client.request(&endpoint, String::new(), hyper::Method::GET).await?;
 						serde_json::from_str(&response).map_err(|e| NetError::DeJson(e, response))
 					}
 				}
 			}
+			// Delete requests have no body and discord responds with no content
+			"DELETE" => {
+				quote! {
+					#decleration (client: &'a mut DiscordClient, #parameters) -> Result<(), NetError>{
+						#endpoint
+						client.request(&endpoint, String::new(), hyper::Method::DELETE).await?;
+						Ok(())
+					}
+				}
+			}
 			request => {
 				let request_name = format_ident!("{}", request);
 
@@ -110,14 +177,30 @@ impl RequestBuilderInput {
 					quote! {String}
 				};
 
+				let body = quote! {serde_json::to_string(#serialize).map_err(|e|NetError::DeJson(e, format!("{:?}", #serialize)))?};
+
 				// Add the function
-				quote! {
-					#decleration (&self, client: &'a mut DiscordClient, #(#fields: impl std::fmt::Display),*) -> Result<#return_ty, NetError>{
+				let mut stream = quote! {
+					#decleration (&self, client: &'a mut DiscordClient, #parameters) -> Result<#return_ty, NetError>{
 						#endpoint
-						let response = client.request(&endpoint, serde_json::to_string(#serialize).map_err(|e|NetError::DeJson(e, format!("{:?}", #serialize)))?, hyper::Method::#request_name).await?;
+						let response = client.request(&endpoint, #body, hyper::Method::#request_name).await?;
 						#return_stmt
 					}
+				};
+
+				// Add the variant which sends files as multipart/form-data
+				if self.upload {
+					let upload_fn_name = format_ident!("{}_with_files", fn_name);
+					stream = quote! {
+						#stream
+						pub async fn #upload_fn_name<'a>(&self, client: &'a mut DiscordClient, #parameters files: &[FileUpload]) -> Result<#return_ty, NetError>{
+							#endpoint
+							let response = client.request_multipart(&endpoint, #body, files, hyper::Method::#request_name).await?;
+							#return_stmt
+						}
+					};
 				}
+				stream
 			}
 		};
 
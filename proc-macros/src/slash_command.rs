@@ -0,0 +1,427 @@
+use proc_macro2::{Span, TokenStream};
+use quote::*;
+use syn::parse::Parse;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{Attribute, Data, DeriveInput, Expr, Fields, Ident, Lit, LitStr, Meta, Token, Type};
+
+/// A `key = value` in a `#[command(...)]` attribute
+struct CommandArgument {
+	key: Ident,
+	_eq: Token!(=),
+	value: Expr,
+}
+
+impl Parse for CommandArgument {
+	fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+		Ok(CommandArgument {
+			key: input.parse()?,
+			_eq: input.parse()?,
+			value: input.parse()?,
+		})
+	}
+}
+
+/// The `#[command(...)]` attributes and doc comments on a type, variant or field
+struct CommandAttributes {
+	arguments: Vec<CommandArgument>,
+	/// The doc comment, used as the description
+	doc: Option<String>,
+	span: Span,
+}
+
+impl CommandAttributes {
+	fn parse(attrs: &[Attribute], span: Span) -> syn::Result<Self> {
+		let mut arguments = Vec::new();
+		let mut doc = Vec::new();
+		for attr in attrs {
+			if attr.path.is_ident("command") {
+				arguments.extend(attr.parse_args_with(Punctuated::<CommandArgument, Token!(,)>::parse_terminated)?);
+			} else if attr.path.is_ident("doc") {
+				if let Meta::NameValue(meta) = attr.parse_meta()? {
+					if let Lit::Str(line) = meta.lit {
+						doc.push(line.value().trim().to_string());
+					}
+				}
+			}
+		}
+		let doc = doc.join(" ").trim().to_string();
+		Ok(Self {
+			arguments,
+			doc: (!doc.is_empty()).then_some(doc),
+			span,
+		})
+	}
+
+	/// Removes an argument, so that any left over can be reported as unknown
+	fn take(&mut self, key: &str) -> Option<Expr> {
+		let index = self.arguments.iter().position(|argument| argument.key == key)?;
+		Some(self.arguments.remove(index).value)
+	}
+
+	/// The name from the `name` argument or the default
+	fn name(&mut self, default: String) -> TokenStream {
+		self.take("name")
+			.map_or_else(|| LitStr::new(&default, self.span).to_token_stream(), |name| name.to_token_stream())
+	}
+
+	/// The doc comment, which discord requires as a description
+	fn description(&self) -> syn::Result<LitStr> {
+		match &self.doc {
+			Some(doc) => Ok(LitStr::new(doc, self.span)),
+			None => Err(syn::Error::new(self.span, "expected a doc comment (used as the description)")),
+		}
+	}
+
+	/// Sets the `name_localizations` and `description_localizations` arguments on an option
+	fn localized(&mut self, option: TokenStream) -> TokenStream {
+		let localizations = ["name_localizations", "description_localizations"].map(|key| {
+			let field = Ident::new(key, self.span);
+			self.take(key)
+				.map(|localizations| quote! {option.#field = Some(::discord::slash_command::localizations(#localizations));})
+		});
+		if localizations.iter().all(Option::is_none) {
+			return option;
+		}
+		quote! {{
+			let mut option = #option;
+			#(#localizations)*
+			option
+		}}
+	}
+
+	fn finish(self) -> syn::Result<()> {
+		match self.arguments.first() {
+			Some(argument) => Err(syn::Error::new(argument.key.span(), "unknown command argument")),
+			None => Ok(()),
+		}
+	}
+}
+
+/// Implements `SlashCommand` and `CommandAutocomplete` for a struct (a command with options) or an enum (with subcommands)
+pub fn slash_command(input: DeriveInput) -> syn::Result<TokenStream> {
+	let ident = &input.ident;
+	let mut attributes = CommandAttributes::parse(&input.attrs, ident.span())?;
+	let name = attributes.name(ident.to_string().to_lowercase());
+	let autocomplete_ty = attributes.take("autocomplete");
+	// The type of the providers, generic if it is not specified
+	let provider_ty = autocomplete_ty.as_ref().map_or_else(|| quote!(A), |ty| ty.to_token_stream());
+	let permissions = attributes.take("default_member_permissions").map(|permissions| {
+		quote! {const DEFAULT_MEMBER_PERMISSIONS: Option<::discord::Permissions> = Some(#permissions);}
+	});
+	let contexts = attributes.take("contexts").map(|contexts| {
+		quote! {const CONTEXTS: Option<&'static [::discord::InteractionContextType]> = Some(&#contexts);}
+	});
+	let nsfw = attributes.take("nsfw").map(|nsfw| quote! {const NSFW: bool = #nsfw;});
+	let localizations = ["name_localizations", "description_localizations"].map(|key| {
+		let function = Ident::new(key, ident.span());
+		attributes.take(key).map(|localizations| {
+			quote! {
+				fn #function() -> Option<::std::collections::HashMap<String, String>> {
+					Some(::discord::slash_command::localizations(#localizations))
+				}
+			}
+		})
+	});
+	// Nested commands use the description of the variant instead
+	let description = attributes.doc.clone().unwrap_or_default();
+	attributes.finish()?;
+
+	let (option_type, options, parse, autocomplete, nested) = match &input.data {
+		Data::Struct(data) => {
+			let mut options = Vec::new();
+			let mut fields = Vec::new();
+			let mut autocomplete = Vec::new();
+			if let Fields::Unnamed(fields) = &data.fields {
+				return Err(syn::Error::new(fields.span(), "expected named fields (the options)"));
+			}
+			for field in &data.fields {
+				let field_ident = field.ident.as_ref().unwrap();
+				let ty = &field.ty;
+				let mut attributes = CommandAttributes::parse(&field.attrs, field_ident.span())?;
+				let option_name = attributes.name(field_ident.to_string());
+				let option_description = attributes.description()?;
+				let mut option = quote! {
+					::discord::ApplicationCommandOption::new()
+						.with_option_type(<#ty as ::discord::CommandOption>::OPTION_TYPE)
+						.with_name(#option_name)
+						.with_description(#option_description)
+						.with_required(<#ty as ::discord::CommandOption>::REQUIRED)
+				};
+				if let Some(range) = attributes.take("range") {
+					option = quote! {#option.with_range(#range)};
+				}
+				for key in ["min_length", "max_length"] {
+					let field = Ident::new(key, field_ident.span());
+					if let Some(length) = attributes.take(key) {
+						option = quote! {
+							::discord::ApplicationCommandOption {
+								#field: Some(#length),
+								..#option
+							}
+						};
+					}
+				}
+				if let Some(channel_types) = attributes.take("channel_types") {
+					option = quote! {
+						::discord::ApplicationCommandOption {
+							channel_types: Some(Vec::from(#channel_types)),
+							..#option
+						}
+					};
+				}
+				if let Some(choices) = attributes.take("choices") {
+					option = quote! {
+						::discord::ApplicationCommandOption {
+							choices: Some(
+								IntoIterator::into_iter(#choices)
+									.map(|(name, value)| <#ty as ::discord::CommandChoice>::choice(name, value))
+									.collect(),
+							),
+							..#option
+						}
+					};
+				}
+				option = attributes.localized(option);
+				if let Some(provider) = attributes.take("autocomplete") {
+					let Some(autocomplete_ty) = &autocomplete_ty else {
+						return Err(syn::Error::new(
+							ident.span(),
+							"expected `#[command(autocomplete = Type)]` with the type of the autocomplete providers",
+						));
+					};
+					option = quote! {#option.with_autocomplete(true)};
+					autocomplete.push(quote! {#option_name => Some({
+						let provider: #autocomplete_ty = #provider;
+						provider
+					}),});
+				}
+				attributes.finish()?;
+
+				options.push(option);
+				fields.push(quote! {#field_ident: <#ty as ::discord::CommandOption>::read(options, #option_name)?,});
+			}
+			let parse = match data.fields {
+				Fields::Unit => quote! {Ok(Self)},
+				_ => quote! {Ok(Self { #(#fields)* })},
+			};
+			// A command with options has no subcommands, so any that are left were not found
+			let parse = quote! {
+				if !subcommands.is_empty() {
+					return Err(::discord::OptionError::UnknownCommand(subcommands.join(" ")));
+				}
+				#parse
+			};
+			let autocomplete = (!autocomplete.is_empty()).then(|| {
+				quote! {
+					match option {
+						#(#autocomplete)*
+						_ => None,
+					}
+				}
+			});
+			(quote!(SubCommand), options, parse, autocomplete, Vec::new())
+		}
+		Data::Enum(data) => {
+			let mut options = Vec::new();
+			let mut parse = Vec::new();
+			let mut autocomplete = Vec::new();
+			let mut nested = Vec::new();
+			for variant in &data.variants {
+				let variant_ident = &variant.ident;
+				let mut attributes = CommandAttributes::parse(&variant.attrs, variant_ident.span())?;
+				let subcommand_name = attributes.name(variant_ident.to_string().to_lowercase());
+				match &variant.fields {
+					Fields::Unit => {
+						let subcommand_description = attributes.description()?;
+						options.push(attributes.localized(quote! {
+							::discord::ApplicationCommandOption::new()
+								.with_option_type(::discord::CommandOptionType::SubCommand)
+								.with_name(#subcommand_name)
+								.with_description(#subcommand_description)
+						}));
+						parse.push(
+							quote! {Some((name, subcommands)) if *name == #subcommand_name && subcommands.is_empty() => Ok(Self::#variant_ident),},
+						);
+					}
+					Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+						let ty = &fields.unnamed[0].ty;
+						let subcommand_description = match &attributes.doc {
+							Some(doc) => doc.to_token_stream(),
+							None => quote! {<#ty as ::discord::SlashCommand>::DESCRIPTION},
+						};
+						options.push(
+							attributes.localized(quote! {<#ty as ::discord::SlashCommand>::subcommand(#subcommand_name, #subcommand_description)}),
+						);
+						parse.push(quote! {
+							Some((name, subcommands)) if *name == #subcommand_name => <#ty as ::discord::SlashCommand>::parse(subcommands, options).map(Self::#variant_ident),
+						});
+						autocomplete.push(quote! {
+							Some((name, subcommands)) if *name == #subcommand_name => <#ty as ::discord::CommandAutocomplete<#provider_ty>>::autocomplete(subcommands, option),
+						});
+						nested.push(ty.clone());
+					}
+					_ => {
+						return Err(syn::Error::new(
+							variant.span(),
+							"expected a unit variant or a variant containing one command",
+						))
+					}
+				}
+				attributes.finish()?;
+			}
+			let parse = quote! {
+				match subcommands.split_first() {
+					#(#parse)*
+					_ => Err(::discord::OptionError::UnknownCommand(subcommands.join(" "))),
+				}
+			};
+			let autocomplete = (!autocomplete.is_empty()).then(|| {
+				quote! {
+					match subcommands.split_first() {
+						#(#autocomplete)*
+						_ => None,
+					}
+				}
+			});
+			(quote!(SubCommandGroup), options, parse, autocomplete, nested)
+		}
+		Data::Union(data) => return Err(syn::Error::new(data.union_token.span(), "expected a struct or an enum")),
+	};
+
+	// Enums are one level deeper than their deepest subcommand, and discord only allows subcommand groups of subcommands
+	let depth = matches!(input.data, Data::Enum(_)).then(|| {
+		quote! {
+			const DEPTH: u8 = {
+				let mut depth = 0;
+				#(if <#nested as ::discord::SlashCommand>::DEPTH > depth {
+					depth = <#nested as ::discord::SlashCommand>::DEPTH;
+				})*
+				depth + 1
+			};
+		}
+	});
+	let depth_checks = nested.iter().map(|ty| {
+		quote_spanned! {ty.span()=>
+			#[allow(clippy::absurd_extreme_comparisons)]
+			const _: () = assert!(
+				<#ty as ::discord::SlashCommand>::DEPTH <= 1,
+				concat!("`", stringify!(#ty), "` has subcommand groups, which can not be nested in another subcommand group")
+			);
+		}
+	});
+
+	let autocomplete = autocomplete_impl(ident, autocomplete_ty, &nested, autocomplete);
+	Ok(quote! {
+		impl ::discord::SlashCommand for #ident {
+			const NAME: &'static str = #name;
+			const DESCRIPTION: &'static str = #description;
+			const OPTION_TYPE: ::discord::CommandOptionType = ::discord::CommandOptionType::#option_type;
+			#permissions
+			#contexts
+			#nsfw
+			#depth
+
+			fn options() -> Vec<::discord::ApplicationCommandOption> {
+				vec![#(#options),*]
+			}
+
+			fn parse(subcommands: &[&str], options: &::discord::CommandOptions) -> Result<Self, ::discord::OptionError> {
+				#parse
+			}
+
+			#(#localizations)*
+		}
+		#(#depth_checks)*
+		#autocomplete
+	})
+}
+
+/// Implements `CommandList` for an enum with a variant for each command
+pub fn command_list(input: DeriveInput) -> syn::Result<TokenStream> {
+	let ident = &input.ident;
+	let mut attributes = CommandAttributes::parse(&input.attrs, ident.span())?;
+	let autocomplete_ty = attributes.take("autocomplete");
+	let provider_ty = autocomplete_ty.as_ref().map_or_else(|| quote!(A), |ty| ty.to_token_stream());
+	attributes.finish()?;
+
+	let Data::Enum(data) = &input.data else {
+		return Err(syn::Error::new(ident.span(), "expected an enum with a variant for each command"));
+	};
+	let mut variants = Vec::new();
+	let mut commands = Vec::new();
+	for variant in &data.variants {
+		match &variant.fields {
+			Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+				variants.push(&variant.ident);
+				commands.push(fields.unnamed[0].ty.clone());
+			}
+			_ => return Err(syn::Error::new(variant.span(), "expected a variant containing one command")),
+		}
+	}
+
+	let autocomplete = autocomplete_impl(
+		ident,
+		autocomplete_ty,
+		&commands,
+		Some(quote! {
+			match subcommands.split_first() {
+				#(Some((name, subcommands)) if *name == <#commands as ::discord::SlashCommand>::NAME => {
+					<#commands as ::discord::CommandAutocomplete<#provider_ty>>::autocomplete(subcommands, option)
+				})*
+				_ => None,
+			}
+		}),
+	);
+	Ok(quote! {
+		impl ::discord::CommandList for #ident {
+			fn commands() -> ::discord::ApplicationCommandList {
+				::discord::ApplicationCommandList::new()
+					#(.with_commands(<#commands as ::discord::SlashCommand>::command()))*
+			}
+
+			fn parse(command: &[&str], options: &::discord::CommandOptions) -> Result<Self, ::discord::OptionError> {
+				let parsed = match command.split_first() {
+					#(Some((name, subcommands)) if *name == <#commands as ::discord::SlashCommand>::NAME => {
+						<#commands as ::discord::SlashCommand>::parse(subcommands, options).map(Self::#variants)
+					})*
+					_ => Err(::discord::OptionError::UnknownCommand(String::new())),
+				};
+				// Report the whole command rather than just the subcommands that were not found
+				match parsed {
+					Err(::discord::OptionError::UnknownCommand(_)) => Err(::discord::OptionError::UnknownCommand(command.join(" "))),
+					parsed => parsed,
+				}
+			}
+		}
+		#autocomplete
+	})
+}
+
+/// Implements `CommandAutocomplete` for the type of the providers, or for every type the nested commands support if it is not specified
+///
+/// The body finds the provider from `subcommands` and `option`, `None` if nothing is autocompleted.
+fn autocomplete_impl(ident: &Ident, autocomplete_ty: Option<Expr>, nested: &[Type], body: Option<TokenStream>) -> TokenStream {
+	let body = body.unwrap_or_else(|| {
+		quote! {
+			let _ = (subcommands, option);
+			None
+		}
+	});
+	match autocomplete_ty {
+		Some(autocomplete_ty) => quote! {
+			impl ::discord::CommandAutocomplete<#autocomplete_ty> for #ident {
+				fn autocomplete(subcommands: &[&str], option: &str) -> Option<#autocomplete_ty> {
+					#body
+				}
+			}
+		},
+		None => quote! {
+			impl<A> ::discord::CommandAutocomplete<A> for #ident where #(#nested: ::discord::CommandAutocomplete<A>,)* {
+				fn autocomplete(subcommands: &[&str], option: &str) -> Option<A> {
+					#body
+				}
+			}
+		},
+	}
+}